@@ -0,0 +1,559 @@
+use tauri::Emitter;
+
+use std::fs;
+use std::path::Path;
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
+
+use crate::cache::{self, CacheEntry, CacheStats, ReplayCache, ReplayIds};
+use crate::replay_parser::{self, AnonymizeOptions, ParseOptions, ParsedReplay, ReplayParser};
+use crate::scr_events::{ScrEvent, ScrProcessEventProvider};
+use tauri::path::BaseDirectory;
+use tauri::Manager;
+use tauri::State;
+use tauri::Window;
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+// Shared snapshot of the last event the polling thread emitted. Lets us
+// answer "what's the current SC state?" for late or reconnecting frontend
+// listeners (initial-load race, dev HMR, manual page reload). Without this,
+// transition-only emits leave a fresh listener stuck on Indeterminate
+// because the backend never gets another transition to broadcast.
+struct LastScrEvent(Mutex<Option<ScrEvent>>);
+
+#[tauri::command]
+fn init_process(window: Window, last_event: State<'_, Arc<LastScrEvent>>) {
+    // Re-emit current state so a freshly-attached listener learns where we
+    // are. Safe to do unconditionally — the frontend treats events
+    // idempotently.
+    if let Some(ev) = last_event.0.lock().unwrap().clone() {
+        let _ = window.emit("scr-event", ev);
+    }
+
+    if INITIALIZED.load(std::sync::atomic::Ordering::Relaxed) {
+        return;
+    }
+    INITIALIZED.store(true, std::sync::atomic::Ordering::Relaxed);
+
+    let last_event: Arc<LastScrEvent> = last_event.inner().clone();
+    std::thread::spawn(move || {
+        let mut _listen =
+            ScrProcessEventProvider::new(Arc::new(Mutex::new(move |event: ScrEvent| {
+                println!("event: {event:?}");
+                *last_event.0.lock().unwrap() = Some(event.clone());
+                let _ = window.emit("scr-event", event);
+            })));
+    });
+}
+
+#[tauri::command]
+fn read_settings_file(path: String) -> Result<String, String> {
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(content),
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Ok(String::new())
+            } else {
+                Err(format!("Failed to read settings file: {e}"))
+            }
+        }
+    }
+}
+
+#[tauri::command]
+fn write_settings_file(path: String, content: String) -> Result<(), String> {
+    if let Some(parent) = Path::new(&path).parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return Err(format!("Failed to create directory: {e}"));
+        }
+    }
+
+    match fs::write(&path, content) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write settings file: {e}")),
+    }
+}
+
+#[tauri::command]
+async fn download_file(
+    url: String,
+    destination_path: String,
+    filename: String,
+    match_id: Option<String>,
+    toon: Option<String>,
+    replay_id: Option<String>,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<String, String> {
+    let ids = ReplayIds {
+        match_id,
+        toon,
+        replay_id,
+    };
+
+    let full_path = Path::new(&destination_path).join(&filename);
+    if let Some(parent) = full_path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return Err(format!("Failed to create directory: {e}"));
+        }
+    }
+
+    let (bytes, _) = fetch_replay_bytes(&url, &ids, &filename, &cache).await?;
+    fs::write(&full_path, &bytes).map_err(|e| format!("Failed to write file: {e}"))?;
+    Ok(full_path.to_string_lossy().to_string())
+}
+
+#[derive(serde::Serialize)]
+struct ParsedChatMessage {
+    sender_name: String,
+    message: String,
+    frame_number: u32,
+    sender_id: u8,
+    timestamp_ms: u32,
+    timestamp_display: String,
+}
+
+#[derive(serde::Serialize)]
+struct ParsedGameHeader {
+    title: String,
+    map_name: String,
+    map_width: u16,
+    map_height: u16,
+    host_name: String,
+    available_slots: u8,
+    game_type: String,
+    game_sub_type: u16,
+    game_speed: String,
+}
+
+#[derive(serde::Serialize)]
+struct ParsedBuildOrderItem {
+    frame_number: u32,
+    timestamp_ms: u32,
+    timestamp_display: String,
+    kind: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+struct ParsedBuildOrder {
+    player_id: u8,
+    name: String,
+    race: String,
+    items: Vec<ParsedBuildOrderItem>,
+}
+
+#[derive(serde::Serialize)]
+struct DownloadAndParseReplayResponse {
+    duration_ms: u32,
+    duration_display: String,
+    start_time_ms: u64,
+    header: ParsedGameHeader,
+    chat_messages: Vec<ParsedChatMessage>,
+    build_orders: Vec<ParsedBuildOrder>,
+    /// Damage skipped over while parsing, e.g. "Replay truncated at frame 12345"
+    warnings: Vec<String>,
+    cached: bool,
+}
+
+/// Parses a downloaded replay. Damaged sections and checksum mismatches
+/// are reported as warnings and everything before them is kept.
+fn parse_replay(bytes: &[u8]) -> Result<ParsedReplay, String> {
+    ReplayParser::new(bytes)
+        .with_options(ParseOptions::lenient())
+        .parse()
+        .map_err(|e| format!("Failed to parse replay: {e}"))
+}
+
+/// Like `parse_replay`, but fails on replays that could only be read in
+/// part, for commands that write a replay back out. Reads the commands
+/// too, as that is where damage to them is found.
+fn parse_complete_replay(bytes: &[u8]) -> Result<ParsedReplay, String> {
+    let parsed = parse_replay(bytes)?;
+    parsed.each_command(|_, _| {});
+    let warnings: Vec<String> = parsed.warnings().map(ToString::to_string).collect();
+    if !warnings.is_empty() {
+        return Err(format!("Replay is damaged: {}", warnings.join("; ")));
+    }
+    Ok(parsed)
+}
+
+fn parse_replay_bytes(
+    bytes: &[u8],
+    cached: bool,
+) -> Result<DownloadAndParseReplayResponse, String> {
+    let parsed = parse_replay(bytes)?;
+
+    let clock = parsed.clock();
+    let duration_ms = parsed.duration_ms();
+    let duration_display = clock.display(parsed.game_info.frames);
+    let start_time_ms = parsed
+        .game_info
+        .start_time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    let info = &parsed.game_info;
+    let header = ParsedGameHeader {
+        title: info.title.clone(),
+        map_name: info.map_name.clone(),
+        map_width: info.map_width,
+        map_height: info.map_height,
+        host_name: info.host_name.clone(),
+        available_slots: info.available_slots,
+        game_type: info.game_type.to_string(),
+        game_sub_type: info.game_sub_type,
+        game_speed: info.game_speed.to_string(),
+    };
+
+    let chat_messages = parsed
+        .chat_messages()
+        .into_iter()
+        .map(|m| ParsedChatMessage {
+            sender_name: m.sender_name,
+            message: m.message,
+            frame_number: m.frame_number,
+            sender_id: m.sender_id,
+            timestamp_ms: m.timestamp_ms,
+            timestamp_display: clock.display(m.frame_number),
+        })
+        .collect();
+
+    let build_orders = parsed
+        .build_orders()
+        .into_iter()
+        .filter_map(|order| {
+            let player = parsed.player(order.player_id)?;
+            Some(ParsedBuildOrder {
+                player_id: player.id,
+                name: player.name.clone(),
+                race: format!("{:?}", player.race),
+                items: order
+                    .items
+                    .into_iter()
+                    .map(|item| ParsedBuildOrderItem {
+                        frame_number: item.frame,
+                        timestamp_ms: item.timestamp_ms,
+                        timestamp_display: clock.display(item.frame),
+                        kind: format!("{:?}", item.kind),
+                        name: item.name,
+                    })
+                    .collect(),
+            })
+        })
+        .collect();
+
+    Ok(DownloadAndParseReplayResponse {
+        duration_ms,
+        duration_display,
+        start_time_ms,
+        header,
+        chat_messages,
+        build_orders,
+        warnings: parsed.warnings().map(ToString::to_string).collect(),
+        cached,
+    })
+}
+
+/// Reads a replay from the cache, or downloads and caches it. Concurrent
+/// requests for the same replay share one download. Also returns whether
+/// it came from the cache.
+async fn fetch_replay_bytes(
+    url: &str,
+    ids: &ReplayIds,
+    filename: &str,
+    cache: &ReplayCache,
+) -> Result<(Vec<u8>, bool), String> {
+    cache
+        .get_or_fetch(url, ids, filename, || download(url))
+        .await
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, String> {
+    use tauri_plugin_http::reqwest;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download file: {e}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Download failed with status: {}",
+            response.status()
+        ));
+    }
+    Ok(response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response: {e}"))?
+        .to_vec())
+}
+
+#[tauri::command]
+async fn download_and_parse_replay(
+    url: String,
+    filename: String,
+    match_id: Option<String>,
+    toon: Option<String>,
+    replay_id: Option<String>,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<DownloadAndParseReplayResponse, String> {
+    let ids = ReplayIds {
+        match_id,
+        toon,
+        replay_id,
+    };
+    let (bytes, cached) = fetch_replay_bytes(&url, &ids, &filename, &cache).await?;
+    parse_replay_bytes(&bytes, cached)
+}
+
+#[derive(serde::Serialize)]
+struct ParsedMinuteApm {
+    minute: u32,
+    apm: u32,
+    eapm: u32,
+}
+
+#[derive(serde::Serialize)]
+struct ParsedPlayerApm {
+    player_id: u8,
+    name: String,
+    race: String,
+    apm: u32,
+    eapm: u32,
+    timeline: Vec<ParsedMinuteApm>,
+}
+
+#[derive(serde::Serialize)]
+struct ReplayApmResponse {
+    players: Vec<ParsedPlayerApm>,
+    /// Damage skipped over while parsing; the APM only covers what was read
+    warnings: Vec<String>,
+}
+
+#[tauri::command]
+async fn get_replay_apm(
+    url: String,
+    filename: String,
+    match_id: Option<String>,
+    toon: Option<String>,
+    replay_id: Option<String>,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<ReplayApmResponse, String> {
+    let ids = ReplayIds {
+        match_id,
+        toon,
+        replay_id,
+    };
+    let (bytes, _) = fetch_replay_bytes(&url, &ids, &filename, &cache).await?;
+    let parsed = parse_replay(&bytes)?;
+
+    let players = parsed
+        .apm()
+        .into_iter()
+        .map(|p| {
+            let player = parsed.player(p.player_id);
+            ParsedPlayerApm {
+                player_id: p.player_id,
+                name: player.map(|pl| pl.name.clone()).unwrap_or_default(),
+                race: player
+                    .map(|pl| format!("{:?}", pl.race))
+                    .unwrap_or_default(),
+                apm: p.apm,
+                eapm: p.eapm,
+                timeline: p
+                    .timeline
+                    .into_iter()
+                    .map(|m| ParsedMinuteApm {
+                        minute: m.minute,
+                        apm: m.apm,
+                        eapm: m.eapm,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(ReplayApmResponse {
+        players,
+        warnings: parsed.warnings().map(ToString::to_string).collect(),
+    })
+}
+
+/// Placeholders for `anonymize_replay`; those not given use the library
+/// defaults
+#[derive(serde::Deserialize, Default)]
+struct AnonymizePlaceholders {
+    player_name: Option<String>,
+    host_name: Option<String>,
+    title: Option<String>,
+    chat_message: Option<String>,
+}
+
+/// Writes an anonymized copy of a replay to `destination_path/filename`.
+/// Damaged replays are refused rather than written out in part.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn anonymize_replay(
+    url: String,
+    destination_path: String,
+    filename: String,
+    match_id: Option<String>,
+    toon: Option<String>,
+    replay_id: Option<String>,
+    placeholders: Option<AnonymizePlaceholders>,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<String, String> {
+    let ids = ReplayIds {
+        match_id,
+        toon,
+        replay_id,
+    };
+    let (bytes, _) = fetch_replay_bytes(&url, &ids, &filename, &cache).await?;
+    let parsed = parse_complete_replay(&bytes)?;
+
+    let placeholders = placeholders.unwrap_or_default();
+    let defaults = AnonymizeOptions::default();
+    let options = AnonymizeOptions {
+        player_name: placeholders.player_name.unwrap_or(defaults.player_name),
+        host_name: placeholders.host_name.unwrap_or(defaults.host_name),
+        title: placeholders.title.unwrap_or(defaults.title),
+        chat_message: placeholders.chat_message.unwrap_or(defaults.chat_message),
+    };
+    let anonymized = replay_parser::anonymize(parsed, &options)
+        .map_err(|e| format!("Failed to anonymize replay: {e}"))?;
+
+    let full_path = Path::new(&destination_path).join(&filename);
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {e}"))?;
+    }
+    fs::write(&full_path, anonymized).map_err(|e| format!("Failed to write file: {e}"))?;
+    Ok(full_path.to_string_lossy().to_string())
+}
+
+/// Writes a replay as screp-compatible JSON to `destination_path/filename`.
+/// Damaged replays are exported as far as they parse.
+#[tauri::command]
+async fn export_replay_json(
+    url: String,
+    destination_path: String,
+    filename: String,
+    match_id: Option<String>,
+    toon: Option<String>,
+    replay_id: Option<String>,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<String, String> {
+    let ids = ReplayIds {
+        match_id,
+        toon,
+        replay_id,
+    };
+    let (bytes, _) = fetch_replay_bytes(&url, &ids, &filename, &cache).await?;
+    let parsed = parse_replay(&bytes)?;
+    let json = serde_json::to_string_pretty(&parsed.to_screp())
+        .map_err(|e| format!("Failed to serialize replay: {e}"))?;
+
+    let full_path = Path::new(&destination_path).join(&filename);
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {e}"))?;
+    }
+    fs::write(&full_path, json).map_err(|e| format!("Failed to write file: {e}"))?;
+    Ok(full_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+fn list_cache_entries(cache: State<'_, Arc<ReplayCache>>) -> Result<Vec<CacheEntry>, String> {
+    cache.entries()
+}
+
+#[tauri::command]
+fn get_cache_stats(cache: State<'_, Arc<ReplayCache>>) -> Result<CacheStats, String> {
+    cache.stats()
+}
+
+#[tauri::command]
+fn evict_cache_entry(key: String, cache: State<'_, Arc<ReplayCache>>) -> Result<(), String> {
+    cache.remove(&key)
+}
+
+#[tauri::command]
+fn clear_cache(cache: State<'_, Arc<ReplayCache>>) -> Result<(), String> {
+    cache.clear()
+}
+
+#[tauri::command]
+fn pin_cache_entry(
+    key: String,
+    pinned: bool,
+    cache: State<'_, Arc<ReplayCache>>,
+) -> Result<(), String> {
+    cache.set_pinned(&key, pinned)
+}
+
+#[tauri::command]
+fn set_cache_capacity(max_bytes: u64, cache: State<'_, Arc<ReplayCache>>) -> Result<(), String> {
+    cache.set_max_bytes(max_bytes)
+}
+
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_http::init())
+        .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            // Establish cache in AppData/cwal-app/replay-cache
+            let app_handle = app.handle();
+            let cache_dir = app_handle
+                .path()
+                .resolve("replay-cache", BaseDirectory::AppData)
+                .unwrap_or_else(|_| {
+                    let mut p = std::env::temp_dir();
+                    p.push("cwal-app-replay-cache");
+                    p
+                });
+            let cache = Arc::new(ReplayCache::new(
+                cache_dir,
+                cache::DEFAULT_MAX_BYTES,
+                cache::default_keys,
+            ));
+            app.manage(cache);
+
+            let last_event: Arc<LastScrEvent> = Arc::new(LastScrEvent(Mutex::new(None)));
+            app.manage(last_event);
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            init_process,
+            read_settings_file,
+            write_settings_file,
+            download_file,
+            download_and_parse_replay,
+            get_replay_apm,
+            anonymize_replay,
+            export_replay_json,
+            list_cache_entries,
+            get_cache_stats,
+            evict_cache_entry,
+            clear_cache,
+            pin_cache_entry,
+            set_cache_capacity,
+            reveal_in_folder
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Keep the order hits left the cache in
+            if let tauri::RunEvent::Exit = event {
+                app.state::<Arc<ReplayCache>>().flush();
+            }
+        });
+}
+
+#[tauri::command]
+fn reveal_in_folder(path: String) -> Result<(), String> {
+    let p = std::path::Path::new(&path);
+    showfile::show_path_in_file_manager(p);
+    Ok(())
+}
@@ -0,0 +1,572 @@
+//! Inspects replays from the command line, without the GUI.
+//!
+//! Usage: cwal-rep <info|chat|commands|apm|events|json|screp> [--json] [--strict] <path>...
+//!
+//! Paths may be .rep files or directories, which are searched recursively.
+
+use cwal_app_lib::replay_parser::{
+    text, Command, GameEventKind, ParseOptions, ParsedReplay, ReplayParser,
+};
+
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::UNIX_EPOCH;
+
+const USAGE: &str = "\
+Usage: cwal-rep <command> [options] <path>...
+
+Commands:
+  info      Map, duration, matchup and players
+  chat      Chat messages
+  commands  Every player command with its frame
+  apm       APM and EAPM per player
+  events    Pauses, speed, alliance and vision changes, and leaves
+  json      Everything above as JSON
+  screp     JSON in the layout of the screp tool
+
+Options:
+  --json    Print JSON instead of tables
+  --strict  Fail on damaged replays instead of reading what is left
+  -h, --help
+
+Paths may be .rep files or directories, which are searched recursively.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subcommand {
+    Info,
+    Chat,
+    Commands,
+    Apm,
+    Events,
+    Json,
+    Screp,
+}
+
+impl Subcommand {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(Subcommand::Info),
+            "chat" => Some(Subcommand::Chat),
+            "commands" => Some(Subcommand::Commands),
+            "apm" => Some(Subcommand::Apm),
+            "events" => Some(Subcommand::Events),
+            "json" => Some(Subcommand::Json),
+            "screp" => Some(Subcommand::Screp),
+            _ => None,
+        }
+    }
+}
+
+struct Args {
+    subcommand: Subcommand,
+    json: bool,
+    strict: bool,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut subcommand = None;
+    let mut json = false;
+    let mut strict = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--strict" => strict = true,
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {flag}")),
+            name if subcommand.is_none() => {
+                subcommand = Some(
+                    Subcommand::parse(name).ok_or_else(|| format!("Unknown command: {name}"))?,
+                );
+            }
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    let subcommand = subcommand.ok_or("Missing command")?;
+    if paths.is_empty() {
+        return Err("Missing replay path".into());
+    }
+    Ok(Args {
+        subcommand,
+        json: json || matches!(subcommand, Subcommand::Json | Subcommand::Screp),
+        strict,
+        paths,
+    })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(args.into_iter()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match run(&args, &mut out) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // e.g. piped into `head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints every replay under the given paths. Returns whether all of them
+/// could be read; problems with single replays go to stderr.
+fn run(args: &Args, out: &mut dyn Write) -> io::Result<bool> {
+    let mut files = Vec::new();
+    let mut ok = true;
+    for path in &args.paths {
+        if let Err(e) = collect_replays(path, &mut files) {
+            eprintln!("{}: {e}", path.display());
+            ok = false;
+        }
+    }
+
+    let options = if args.strict {
+        ParseOptions::strict()
+    } else {
+        ParseOptions::lenient()
+    };
+
+    let mut outputs = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let replay = match read_replay(file, options) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("{}: {e}", file.display());
+                ok = false;
+                continue;
+            }
+        };
+        if args.json {
+            let mut value = to_json(args.subcommand, &replay);
+            value["file"] = json!(file.display().to_string());
+            outputs.push(value);
+        } else {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            if files.len() > 1 {
+                writeln!(out, "== {} ==", file.display())?;
+            }
+            print_tables(out, args.subcommand, &replay)?;
+        }
+        // After the output, which is what reads the commands
+        for warning in replay.warnings() {
+            eprintln!("{}: warning: {warning}", file.display());
+        }
+    }
+
+    if args.json {
+        // A single replay prints as an object so scripts can use it directly
+        let output = match outputs.len() {
+            1 => outputs.remove(0),
+            _ => Value::Array(outputs),
+        };
+        serde_json::to_writer_pretty(&mut *out, &output)?;
+        writeln!(out)?;
+    }
+
+    Ok(ok)
+}
+
+/// Adds `path` if it is a file, or every .rep file below it if it is a
+/// directory, in a stable order
+fn collect_replays(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        // Named files are taken whatever their extension
+        std::fs::metadata(path)?;
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_replays(&entry, files)?;
+        } else if entry
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("rep"))
+        {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+fn read_replay(path: &Path, options: ParseOptions) -> Result<ParsedReplay, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    ReplayParser::new(&bytes)
+        .with_options(options)
+        .parse()
+        .map_err(|e| e.to_string())
+}
+
+fn print_tables(
+    out: &mut dyn Write,
+    subcommand: Subcommand,
+    replay: &ParsedReplay,
+) -> io::Result<()> {
+    match subcommand {
+        Subcommand::Info | Subcommand::Json | Subcommand::Screp => print_info(out, replay),
+        Subcommand::Chat => print_chat(out, replay),
+        Subcommand::Commands => print_commands(out, replay),
+        Subcommand::Apm => print_apm(out, replay),
+        Subcommand::Events => print_events(out, replay),
+    }
+}
+
+fn print_info(out: &mut dyn Write, replay: &ParsedReplay) -> io::Result<()> {
+    let info = &replay.game_info;
+    let clock = replay.clock();
+    writeln!(out, "Map:      {}", info.map_name)?;
+    writeln!(out, "Title:    {}", info.title)?;
+    writeln!(out, "Host:     {}", info.host_name)?;
+    writeln!(out, "Type:     {:?}", info.game_type)?;
+    writeln!(out, "Speed:    {:?}", info.game_speed)?;
+    writeln!(out, "Duration: {}", clock.display(info.frames))?;
+    writeln!(out, "Matchup:  {}", replay.matchup())?;
+    writeln!(out)?;
+
+    let results = replay.result();
+    let rows = info
+        .player_structs
+        .iter()
+        .filter(|p| p.is_active())
+        .map(|p| {
+            let result = results.iter().find(|r| r.player_id == p.id);
+            vec![
+                p.id.to_string(),
+                p.name.clone(),
+                format!("{:?}", p.race),
+                p.team.to_string(),
+                format!("{:?}", p.player_type),
+                if p.is_observer { "yes" } else { "" }.to_string(),
+                result.map_or(String::new(), |r| format!("{:?}", r.outcome)),
+            ]
+        })
+        .collect();
+    print_table(
+        out,
+        &["ID", "Name", "Race", "Team", "Type", "Observer", "Result"],
+        rows,
+    )
+}
+
+fn print_chat(out: &mut dyn Write, replay: &ParsedReplay) -> io::Result<()> {
+    let clock = replay.clock();
+    let rows = replay
+        .chat_messages()
+        .into_iter()
+        .map(|m| vec![clock.display(m.frame_number), m.sender_name, m.message])
+        .collect();
+    print_table(out, &["Time", "Player", "Message"], rows)
+}
+
+fn print_commands(out: &mut dyn Write, replay: &ParsedReplay) -> io::Result<()> {
+    let clock = replay.clock();
+    let mut rows = Vec::new();
+    replay.each_command(|frame_number, command| {
+        rows.push(vec![
+            clock.display(frame_number),
+            frame_number.to_string(),
+            player_name(replay, command.player_id),
+            describe(&command.command),
+        ]);
+    });
+    print_table(out, &["Time", "Frame", "Player", "Command"], rows)
+}
+
+fn print_apm(out: &mut dyn Write, replay: &ParsedReplay) -> io::Result<()> {
+    let rows = replay
+        .apm()
+        .into_iter()
+        .map(|apm| {
+            vec![
+                player_name(replay, apm.player_id),
+                apm.apm.to_string(),
+                apm.eapm.to_string(),
+            ]
+        })
+        .collect();
+    print_table(out, &["Player", "APM", "EAPM"], rows)
+}
+
+fn print_events(out: &mut dyn Write, replay: &ParsedReplay) -> io::Result<()> {
+    let clock = replay.clock();
+    let rows = replay
+        .game_events()
+        .into_iter()
+        .map(|event| {
+            let (name, details) = match event.kind {
+                GameEventKind::Pause { resume } => (
+                    "Pause",
+                    match resume {
+                        Some(resume) => format!(
+                            "resumed by {} at {}",
+                            player_name(replay, resume.player_id),
+                            clock.display(resume.frame)
+                        ),
+                        None => "never resumed".to_string(),
+                    },
+                ),
+                GameEventKind::SpeedChange(speed) => ("Speed", format!("{speed:?}")),
+                GameEventKind::Alliance {
+                    slots,
+                    allied_victory,
+                } => (
+                    "Alliance",
+                    format!(
+                        "slots {slots:?}{}",
+                        if allied_victory {
+                            ", allied victory"
+                        } else {
+                            ""
+                        }
+                    ),
+                ),
+                GameEventKind::Vision { slots } => ("Vision", format!("slots {slots:?}")),
+                GameEventKind::Leave(reason) => ("Leave", format!("{reason:?}")),
+            };
+            vec![
+                clock.display(event.frame),
+                player_name(replay, event.player_id),
+                name.to_string(),
+                details,
+            ]
+        })
+        .collect();
+    print_table(out, &["Time", "Player", "Event", "Details"], rows)
+}
+
+/// Like the debug form, with text shown as text and unknown data as hex
+fn describe(command: &Command) -> String {
+    match command {
+        Command::Chat { sender, message } => {
+            format!(
+                "Chat {{ sender: {sender}, message: {:?} }}",
+                text::decode(message)
+            )
+        }
+        Command::SaveGame { filename, .. } => format!("SaveGame({:?})", text::decode(filename)),
+        Command::LoadGame { filename, .. } => format!("LoadGame({:?})", text::decode(filename)),
+        Command::Unknown { opcode, data } => {
+            let hex: String = data.iter().map(|b| format!("{b:02x}")).collect();
+            format!("Unknown {{ opcode: 0x{opcode:02x}, data: {hex} }}")
+        }
+        command => format!("{command:?}"),
+    }
+}
+
+fn player_name(replay: &ParsedReplay, player_id: u8) -> String {
+    replay
+        .player(player_id)
+        .map(|p| p.name.clone())
+        .unwrap_or_else(|| format!("#{player_id}"))
+}
+
+/// Prints rows with each column padded to its widest cell
+fn print_table(out: &mut dyn Write, headers: &[&str], rows: Vec<Vec<String>>) -> io::Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut print_row = |cells: &[String]| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())
+    };
+    print_row(&headers.iter().map(|h| h.to_string()).collect::<Vec<_>>())?;
+    print_row(&widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>())?;
+    for row in &rows {
+        print_row(row)?;
+    }
+    Ok(())
+}
+
+fn to_json(subcommand: Subcommand, replay: &ParsedReplay) -> Value {
+    let mut value = match subcommand {
+        Subcommand::Info => json!({ "info": info_json(replay) }),
+        Subcommand::Chat => json!({ "chat": chat_json(replay) }),
+        Subcommand::Commands => json!({ "commands": commands_json(replay) }),
+        Subcommand::Apm => json!({ "apm": apm_json(replay) }),
+        Subcommand::Events => json!({ "events": events_json(replay) }),
+        Subcommand::Json => json!({
+            "info": info_json(replay),
+            "chat": chat_json(replay),
+            "apm": apm_json(replay),
+            "events": events_json(replay),
+            "commands": commands_json(replay),
+        }),
+        Subcommand::Screp => {
+            return serde_json::to_value(replay.to_screp()).expect("screp output always serializes")
+        }
+    };
+    // After the rest, which is what reads the commands
+    let warnings: Vec<String> = replay.warnings().map(ToString::to_string).collect();
+    value["warnings"] = json!(warnings);
+    value
+}
+
+fn info_json(replay: &ParsedReplay) -> Value {
+    let info = &replay.game_info;
+    let start_time = info
+        .start_time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let results = replay.result();
+    let players: Vec<Value> = info
+        .player_structs
+        .iter()
+        .filter(|p| p.is_active())
+        .map(|p| {
+            let result = results.iter().find(|r| r.player_id == p.id);
+            json!({
+                "id": p.id,
+                "slot_id": p.slot_id,
+                "name": p.name,
+                "race": format!("{:?}", p.race),
+                "team": p.team,
+                "type": format!("{:?}", p.player_type),
+                "color": p.color,
+                "observer": p.is_observer,
+                "outcome": result.map(|r| format!("{:?}", r.outcome)),
+                "leave_frame": result.and_then(|r| r.leave_frame),
+                "leave_reason": result.and_then(|r| r.leave_reason).map(|r| format!("{r:?}")),
+            })
+        })
+        .collect();
+
+    json!({
+        "map_name": info.map_name,
+        "map_width": info.map_width,
+        "map_height": info.map_height,
+        "title": info.title,
+        "host_name": info.host_name,
+        "game_type": format!("{:?}", info.game_type),
+        "game_speed": format!("{:?}", info.game_speed),
+        "engine": format!("{:?}", info.engine),
+        "start_time": start_time,
+        "frames": info.frames,
+        "duration_ms": replay.duration_ms(),
+        "matchup": replay.matchup(),
+        "players": players,
+    })
+}
+
+fn chat_json(replay: &ParsedReplay) -> Value {
+    replay
+        .chat_messages()
+        .into_iter()
+        .map(|m| {
+            json!({
+                "frame": m.frame_number,
+                "timestamp_ms": m.timestamp_ms,
+                "sender_id": m.sender_id,
+                "sender_name": m.sender_name,
+                "message": m.message,
+            })
+        })
+        .collect()
+}
+
+fn commands_json(replay: &ParsedReplay) -> Value {
+    let clock = replay.clock();
+    let mut commands = Vec::new();
+    replay.each_command(|frame_number, command| {
+        commands.push(json!({
+            "frame": frame_number,
+            "timestamp_ms": clock.ms(frame_number),
+            "player_id": command.player_id,
+            "opcode": command.command.opcode(),
+            "command": describe(&command.command),
+        }));
+    });
+    Value::Array(commands)
+}
+
+fn events_json(replay: &ParsedReplay) -> Value {
+    replay
+        .game_events()
+        .into_iter()
+        .map(|event| {
+            let mut value = json!({
+                "frame": event.frame,
+                "timestamp_ms": event.timestamp_ms,
+                "player_id": event.player_id,
+            });
+            let details = match event.kind {
+                GameEventKind::Pause { resume } => json!({
+                    "type": "pause",
+                    "resumed_by": resume.as_ref().map(|r| r.player_id),
+                    "resumed_frame": resume.as_ref().map(|r| r.frame),
+                }),
+                GameEventKind::SpeedChange(speed) => {
+                    json!({ "type": "speed", "speed": format!("{speed:?}") })
+                }
+                GameEventKind::Alliance {
+                    slots,
+                    allied_victory,
+                } => {
+                    json!({ "type": "alliance", "slots": slots, "allied_victory": allied_victory })
+                }
+                GameEventKind::Vision { slots } => json!({ "type": "vision", "slots": slots }),
+                GameEventKind::Leave(reason) => {
+                    json!({ "type": "leave", "reason": format!("{reason:?}") })
+                }
+            };
+            if let (Value::Object(value), Value::Object(details)) = (&mut value, details) {
+                value.extend(details);
+            }
+            value
+        })
+        .collect()
+}
+
+fn apm_json(replay: &ParsedReplay) -> Value {
+    replay
+        .apm()
+        .into_iter()
+        .map(|apm| {
+            let timeline: Vec<Value> = apm
+                .timeline
+                .iter()
+                .map(|m| json!({ "minute": m.minute, "apm": m.apm, "eapm": m.eapm }))
+                .collect();
+            json!({
+                "player_id": apm.player_id,
+                "name": player_name(replay, apm.player_id),
+                "apm": apm.apm,
+                "eapm": apm.eapm,
+                "timeline": timeline,
+            })
+        })
+        .collect()
+}
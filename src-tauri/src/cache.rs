@@ -1,89 +1,794 @@
+use crate::replay_parser::{ParseOptions, ReplayParser};
 use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
+    collections::{HashMap, HashSet},
     fs,
-    hash::{DefaultHasher, Hash, Hasher},
-    num::NonZeroUsize,
-    path::PathBuf,
-    sync::Mutex,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::OnceCell;
 
+/// Budget used when the app doesn't pick one
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Lists the cached keys, least recently used first
+const INDEX_FILE: &str = "index.json";
+
+/// Query parameters of signed URLs that change every time a link is issued
+const VOLATILE_PARAMS: &[&str] = &[
+    "expires",
+    "signature",
+    "key-pair-id",
+    "policy",
+    "token",
+    "sig",
+    "se",
+    "st",
+    "sp",
+    "sv",
+    "sr",
+];
+
+/// Identifies a replay independently of the URL it is fetched from
+#[derive(Debug, Clone, Default)]
+pub struct ReplayIds {
+    pub match_id: Option<String>,
+    /// The player whose replay it is, as each player of a match uploads
+    /// their own
+    pub toon: Option<String>,
+    pub replay_id: Option<String>,
+}
+
+/// Derives the keys a replay is cached under, most specific first. A lookup
+/// tries each of them and a store writes all of them, sharing one file.
+pub type KeyFn = fn(url: &str, ids: &ReplayIds) -> Vec<String>;
+
+/// Keys on the match when the caller knows all of its ids, and always on
+/// the URL without its volatile query parameters
+pub fn default_keys(url: &str, ids: &ReplayIds) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let ReplayIds {
+        match_id: Some(match_id),
+        toon: Some(toon),
+        replay_id: Some(replay_id),
+    } = ids
+    {
+        keys.push(format!("match:{match_id}:{toon}:{replay_id}"));
+    }
+    keys.push(normalize_url(url));
+    keys
+}
+
+/// Drops the fragment and volatile query parameters, and sorts the rest
+pub fn normalize_url(url: &str) -> String {
+    let url = url.split('#').next().unwrap_or(url);
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or("").to_ascii_lowercase();
+            !name.is_empty()
+                && !name.starts_with("x-amz-")
+                && !VOLATILE_PARAMS.contains(&name.as_str())
+        })
+        .collect();
+    if params.is_empty() {
+        return base.to_string();
+    }
+    params.sort_unstable();
+    format!("{base}?{}", params.join("&"))
+}
+
+/// What a cached replay is, so it can be listed without parsing it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySummary {
+    pub map_name: String,
+    pub matchup: String,
+    pub duration_ms: u32,
+    pub players: Vec<String>,
+}
+
+impl ReplaySummary {
+    /// `None` for files that aren't replays, e.g. maps
+    fn of(bytes: &[u8]) -> Option<Self> {
+        let replay = ReplayParser::new(bytes)
+            .with_options(ParseOptions::lenient())
+            .parse()
+            .ok()?;
+        Some(Self {
+            map_name: replay.game_info.map_name.clone(),
+            matchup: replay.matchup(),
+            duration_ms: replay.duration_ms(),
+            players: replay.players().map(|p| p.name.clone()).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// SHA-256 of the replay, hex encoded. Also names its file.
+    hash: String,
+    /// URL the replay was last requested with
+    url: String,
+    /// Seconds since the Unix epoch
+    last_access: u64,
+    /// Pinned entries are never evicted
+    pinned: bool,
+    summary: Option<ReplaySummary>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    key: String,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct IndexFile {
+    /// Budget set at runtime, which wins over the one the app starts with
+    max_bytes: Option<u64>,
+    entries: Vec<IndexEntry>,
+}
+
+/// A cache entry as listed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub url: String,
+    pub size: u64,
+    pub last_access: u64,
+    pub pinned: bool,
+    pub summary: Option<ReplaySummary>,
+}
+
+/// Hits and misses count since the app started
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub replays: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct Index {
+    keys: LruCache<String, Entry>, // key: from the KeyFn
+    /// Size of each stored replay by hash. Keys for the same replay share it.
+    blobs: HashMap<String, u64>,
+    total_bytes: u64,
+    max_bytes: u64,
+    max_bytes_set: bool,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    /// Hits reorder the entries without saving the index right away
+    dirty: bool,
+}
+
+impl Index {
+    fn is_referenced(&self, hash: &str) -> bool {
+        self.keys.iter().any(|(_, e)| e.hash == hash)
+    }
+}
+
+/// A download in progress. Everyone asking for the same replay meanwhile
+/// waits on it instead of downloading again.
+type Flight = Arc<OnceCell<Result<Arc<[u8]>, String>>>;
+
+/// Replays downloaded before, kept on disk across restarts and stored by
+/// the SHA-256 of their contents. The least recently used ones that aren't
+/// pinned are deleted once the files add up to more than `max_bytes`.
 pub struct ReplayCache {
     dir: PathBuf,
-    lru: Mutex<LruCache<String, PathBuf>>, // key: url, value: cached filepath
+    index: Mutex<Index>,
+    keys: KeyFn,
+    /// By every key of the replay being downloaded
+    flights: Mutex<HashMap<String, Flight>>,
 }
 
 impl ReplayCache {
-    pub fn new(dir: PathBuf, cap: usize) -> Self {
+    /// `keys` derives what entries are stored under, e.g. `default_keys`
+    pub fn new(dir: PathBuf, max_bytes: u64, keys: KeyFn) -> Self {
         if let Err(e) = fs::create_dir_all(&dir) {
             println!("[replay-cache] Failed to create cache dir {dir:?}: {e}");
         }
-        Self {
+        let mut file = load_index(&dir);
+        let mut index = Index {
+            keys: LruCache::unbounded(),
+            blobs: HashMap::new(),
+            total_bytes: 0,
+            max_bytes: file.max_bytes.unwrap_or(max_bytes),
+            max_bytes_set: file.max_bytes.is_some(),
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            dirty: false,
+        };
+
+        file.entries.sort_by_key(|e| e.entry.last_access);
+        for IndexEntry { key, entry } in file.entries {
+            // Files can be deleted while the app isn't running. Their
+            // contents are checked when they are read.
+            if !index.blobs.contains_key(&entry.hash) {
+                match fs::metadata(dir.join(blob_file_name(&entry.hash))) {
+                    Ok(meta) if meta.is_file() => {
+                        index.total_bytes += meta.len();
+                        index.blobs.insert(entry.hash.clone(), meta.len());
+                    }
+                    _ => continue,
+                }
+            }
+            index.keys.push(key, entry);
+        }
+        remove_unindexed_files(&dir, &index);
+
+        let cache = Self {
             dir,
-            lru: Mutex::new(LruCache::new(
-                NonZeroUsize::new(cap).unwrap_or(NonZeroUsize::new(1000).unwrap()),
-            )),
+            index: Mutex::new(index),
+            keys,
+            flights: Mutex::new(HashMap::new()),
+        };
+        if let Ok(mut index) = cache.index.lock() {
+            cache.evict(&mut index);
+            cache.save(&mut index);
         }
+        cache
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Index>, String> {
+        self.index
+            .lock()
+            .map_err(|_| "Replay cache index is poisoned".to_string())
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(blob_file_name(hash))
     }
 
-    fn key(url: &str) -> String {
-        // Basic key; consider hashing if URLs are long
-        url.to_string()
+    /// The cached replay, or the one `fetch` downloads, which is then
+    /// cached. Concurrent calls for the same replay share one `fetch`. Also
+    /// returns whether the replay came without downloading it.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        url: &str,
+        ids: &ReplayIds,
+        filename: &str,
+        fetch: F,
+    ) -> Result<(Vec<u8>, bool), String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, String>>,
+    {
+        if let Some(bytes) = self.get(url, ids) {
+            return Ok((bytes, true));
+        }
+
+        let keys = (self.keys)(url, ids);
+        let flight = {
+            let mut flights = self
+                .flights
+                .lock()
+                .map_err(|_| "Replay download map is poisoned".to_string())?;
+            match keys.iter().find_map(|key| flights.get(key)) {
+                Some(flight) => flight.clone(),
+                None => {
+                    let flight = Flight::default();
+                    for key in &keys {
+                        flights.insert(key.clone(), flight.clone());
+                    }
+                    flight
+                }
+            }
+        };
+
+        let mut downloaded = false;
+        let result = flight
+            .get_or_init(|| async {
+                // A flight that just finished may have cached it
+                if let Some(bytes) = self.lookup(url, ids) {
+                    return Ok(bytes.into());
+                }
+                downloaded = true;
+                let bytes = fetch().await?;
+                if let Err(e) = self.put(url, ids, filename, &bytes) {
+                    println!("[replay-cache] Failed to cache {url}: {e}");
+                }
+                Ok(bytes.into())
+            })
+            .await
+            .clone();
+
+        if let Ok(mut flights) = self.flights.lock() {
+            flights.retain(|_, f| !Arc::ptr_eq(f, &flight));
+        }
+        result.map(|bytes| (bytes.to_vec(), !downloaded))
     }
 
-    fn hashed_path_for(&self, url: &str) -> PathBuf {
-        let mut hasher = DefaultHasher::new();
-        url.hash(&mut hasher);
-        let h = hasher.finish();
-        // keep extension generic; consumers copy with their own filename
-        self.dir.join(format!("{h:016x}.rep"))
+    /// The cached replay, after checking that its contents still match
+    /// their hash. Missing or corrupt files are evicted.
+    pub fn get(&self, url: &str, ids: &ReplayIds) -> Option<Vec<u8>> {
+        let bytes = self.lookup(url, ids);
+        if bytes.is_none() {
+            if let Ok(mut index) = self.lock() {
+                index.misses += 1;
+            }
+        }
+        bytes
     }
 
-    pub fn get(&self, url: &str) -> Option<PathBuf> {
-        let key = Self::key(url);
-        let mut lru = self.lru.lock().ok()?;
-        let hit = lru.get(&key).cloned();
-        if let Some(p) = hit {
-            if p.exists() {
-                println!("[replay-cache] HIT for {} -> {}", url, p.display());
-                return Some(p.clone());
-            } else {
-                // stale entry
-                println!("[replay-cache] STALE entry for {} -> {}", url, p.display());
-                lru.pop(&key);
+    /// Reads and checks the file without holding the index lock, so other
+    /// lookups aren't held up by the disk. Hits only mark the index to be
+    /// saved later, see `flush`.
+    fn lookup(&self, url: &str, ids: &ReplayIds) -> Option<Vec<u8>> {
+        let keys = (self.keys)(url, ids);
+        for key in &keys {
+            let Some(entry) = self.lock().ok()?.keys.peek(key).cloned() else {
+                continue;
+            };
+            let bytes = fs::read(self.blob_path(&entry.hash))
+                .ok()
+                .filter(|bytes| sha256_hex(bytes) == entry.hash);
+
+            let mut index = self.lock().ok()?;
+            match bytes {
+                Some(bytes) => {
+                    index.hits += 1;
+                    // Unless it was evicted while being read, also makes the
+                    // replay reachable by the keys that missed
+                    if index.blobs.contains_key(&entry.hash) {
+                        self.link(&mut index, keys, url, &entry.hash, entry.summary);
+                        index.dirty = true;
+                    }
+                    return Some(bytes);
+                }
+                // Corrupt, truncated or deleted
+                None => {
+                    self.remove_blob(&mut index, &entry.hash);
+                    self.save(&mut index);
+                }
             }
         }
-        println!("[replay-cache] MISS for {url}");
         None
     }
 
-    pub fn put(&self, url: &str, _filename_hint: &str, bytes: &[u8]) -> Result<PathBuf, String> {
-        use std::io::Write;
-        let path = self.hashed_path_for(url);
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| format!("Failed to create cache dir: {e}"))?;
+    /// Saves the index if hits changed it since it was last saved
+    pub fn flush(&self) {
+        if let Ok(mut index) = self.lock() {
+            if index.dirty {
+                self.save(&mut index);
+            }
         }
-        let mut f =
-            fs::File::create(&path).map_err(|e| format!("Failed to create cache file: {e}"))?;
-        f.write_all(bytes)
-            .map_err(|e| format!("Failed to write cache file: {e}"))?;
-        let key = Self::key(url);
-        if let Ok(mut lru) = self.lru.lock() {
-            lru.put(key, path.clone());
+    }
+
+    pub fn put(
+        &self,
+        url: &str,
+        ids: &ReplayIds,
+        _filename_hint: &str,
+        bytes: &[u8],
+    ) -> Result<PathBuf, String> {
+        let hash = sha256_hex(bytes);
+        let path = self.blob_path(&hash);
+        let summary = ReplaySummary::of(bytes);
+        let mut index = self.lock()?;
+
+        // Another URL may already have stored the same replay
+        if !index.blobs.contains_key(&hash) || !path.exists() {
+            fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to create cache dir: {e}"))?;
+            write_atomic(&path, bytes).map_err(|e| format!("Failed to write cache file: {e}"))?;
+            let size = bytes.len() as u64;
+            if let Some(old) = index.blobs.insert(hash.clone(), size) {
+                index.total_bytes -= old;
+            }
+            index.total_bytes += size;
         }
+
+        self.link(&mut index, (self.keys)(url, ids), url, &hash, summary);
+        self.evict(&mut index);
+        self.save(&mut index);
         Ok(path)
     }
+
+    /// Entries, most recently used first
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, String> {
+        let index = self.lock()?;
+        Ok(index
+            .keys
+            .iter()
+            .map(|(key, entry)| CacheEntry {
+                key: key.clone(),
+                url: entry.url.clone(),
+                size: index.blobs.get(&entry.hash).copied().unwrap_or(0),
+                last_access: entry.last_access,
+                pinned: entry.pinned,
+                summary: entry.summary.clone(),
+            })
+            .collect())
+    }
+
+    pub fn stats(&self) -> Result<CacheStats, String> {
+        let index = self.lock()?;
+        Ok(CacheStats {
+            entries: index.keys.len(),
+            replays: index.blobs.len(),
+            total_bytes: index.total_bytes,
+            max_bytes: index.max_bytes,
+            hits: index.hits,
+            misses: index.misses,
+            evictions: index.evictions,
+        })
+    }
+
+    /// Removes an entry. Its file stays while other keys refer to it.
+    pub fn remove(&self, key: &str) -> Result<(), String> {
+        let mut index = self.lock()?;
+        let entry = index
+            .keys
+            .pop(key)
+            .ok_or_else(|| format!("No cache entry for {key}"))?;
+        self.release_blob(&mut index, &entry.hash);
+        self.save(&mut index);
+        Ok(())
+    }
+
+    /// Removes every entry, pinned ones included
+    pub fn clear(&self) -> Result<(), String> {
+        let mut index = self.lock()?;
+        index.keys.clear();
+        let hashes: Vec<String> = index.blobs.keys().cloned().collect();
+        for hash in hashes {
+            self.release_blob(&mut index, &hash);
+        }
+        self.save(&mut index);
+        Ok(())
+    }
+
+    pub fn set_pinned(&self, key: &str, pinned: bool) -> Result<(), String> {
+        let mut index = self.lock()?;
+        let entry = index
+            .keys
+            .peek_mut(key)
+            .ok_or_else(|| format!("No cache entry for {key}"))?;
+        entry.pinned = pinned;
+        self.evict(&mut index);
+        self.save(&mut index);
+        Ok(())
+    }
+
+    /// Changes the budget, evicting right away if the cache is now over it.
+    /// The new budget is kept across restarts.
+    pub fn set_max_bytes(&self, max_bytes: u64) -> Result<(), String> {
+        let mut index = self.lock()?;
+        index.max_bytes = max_bytes;
+        index.max_bytes_set = true;
+        self.evict(&mut index);
+        self.save(&mut index);
+        Ok(())
+    }
+
+    /// Points the keys at a stored replay and marks them as just used
+    fn link(
+        &self,
+        index: &mut Index,
+        keys: Vec<String>,
+        url: &str,
+        hash: &str,
+        summary: Option<ReplaySummary>,
+    ) {
+        for key in keys {
+            let entry = Entry {
+                hash: hash.to_string(),
+                url: url.to_string(),
+                last_access: now(),
+                pinned: index.keys.peek(&key).is_some_and(|e| e.pinned),
+                summary: summary.clone(),
+            };
+            if let Some((_, old)) = index.keys.push(key, entry) {
+                if old.hash != hash {
+                    self.release_blob(index, &old.hash);
+                }
+            }
+        }
+    }
+
+    /// Deletes least recently used entries that aren't pinned until the
+    /// cache fits its budget. The most recent replay stays, under all of
+    /// its keys, even if it alone is over budget.
+    fn evict(&self, index: &mut Index) {
+        let most_recent = index.keys.peek_mru().map(|(_, entry)| entry.hash.clone());
+        while index.total_bytes > index.max_bytes {
+            let Some(key) = index
+                .keys
+                .iter()
+                .rev()
+                .find(|&(_, entry)| !entry.pinned && Some(&entry.hash) != most_recent.as_ref())
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = index.keys.pop(&key) {
+                index.evictions += 1;
+                self.release_blob(index, &entry.hash);
+            }
+        }
+    }
+
+    /// Deletes a replay once no key refers to it anymore
+    fn release_blob(&self, index: &mut Index, hash: &str) {
+        if index.is_referenced(hash) {
+            return;
+        }
+        if let Some(size) = index.blobs.remove(hash) {
+            index.total_bytes -= size;
+        }
+        let path = self.blob_path(hash);
+        if let Err(e) = fs::remove_file(&path) {
+            if path.exists() {
+                println!("[replay-cache] Failed to delete {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Deletes a replay and every key that refers to it
+    fn remove_blob(&self, index: &mut Index, hash: &str) {
+        let keys: Vec<String> = index
+            .keys
+            .iter()
+            .filter(|(_, e)| e.hash == hash)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in keys {
+            index.keys.pop(&key);
+        }
+        self.release_blob(index, hash);
+    }
+
+    /// Writes the index next to the cached files. Failures only cost the
+    /// cache its contents on the next start, so they are logged and ignored.
+    fn save(&self, index: &mut Index) {
+        index.dirty = false;
+        let file = IndexFile {
+            max_bytes: index.max_bytes_set.then_some(index.max_bytes),
+            entries: index
+                .keys
+                .iter()
+                .rev()
+                .map(|(key, entry)| IndexEntry {
+                    key: key.clone(),
+                    entry: entry.clone(),
+                })
+                .collect(),
+        };
+        let result = serde_json::to_vec(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                write_atomic(&self.dir.join(INDEX_FILE), &json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("[replay-cache] Failed to save index: {e}");
+        }
+    }
 }
 
 impl Drop for ReplayCache {
     fn drop(&mut self) {
-        if let Ok(mut lru) = self.lru.lock() {
-            for (_k, p) in lru.iter() {
-                if p.exists() {
-                    let _ = fs::remove_file(p);
-                }
-            }
-            lru.clear();
+        self.flush();
+    }
+}
+
+fn load_index(dir: &Path) -> IndexFile {
+    let Ok(json) = fs::read(dir.join(INDEX_FILE)) else {
+        return IndexFile::default();
+    };
+    serde_json::from_slice(&json).unwrap_or_else(|e| {
+        println!("[replay-cache] Ignoring unreadable index: {e}");
+        IndexFile::default()
+    })
+}
+
+/// Writes to a temporary file and renames it into place, so readers never
+/// see half a file, even if the app dies while writing
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    // Unique per write, so concurrent writes of one file don't mix
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let tmp = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, bytes)
+        .and_then(|_| fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+}
+
+/// Deletes replays the index doesn't know about, e.g. from versions that
+/// named files differently. Their URLs are lost, so they could never be hit.
+/// Also deletes temporary files left by writes that never finished.
+fn remove_unindexed_files(dir: &Path, index: &Index) {
+    let indexed: HashSet<String> = index.blobs.keys().map(|h| blob_file_name(h)).collect();
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in read_dir.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let unindexed = extension == Some("rep") && !indexed.contains(name);
+        if unindexed || extension == Some("tmp") {
+            let _ = fs::remove_file(&path);
         }
-        println!("[replay-cache] Cache cleared on drop");
+    }
+}
+
+fn blob_file_name(hash: &str) -> String {
+    // keep extension generic; consumers copy with their own filename
+    format!("{hash}.rep")
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cwal-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn hits_are_saved_lazily() {
+        let dir = temp_dir("lazy");
+        let cache = ReplayCache::new(dir.clone(), DEFAULT_MAX_BYTES, default_keys);
+        let ids = ReplayIds::default();
+        cache
+            .put("https://cdn.example/r.rep", &ids, "r.rep", b"replay")
+            .unwrap();
+        assert!(!cache.lock().unwrap().dirty);
+
+        let bytes = cache.get("https://cdn.example/r.rep?Expires=2", &ids);
+        assert_eq!(bytes.as_deref(), Some(&b"replay"[..]));
+        assert!(cache.lock().unwrap().dirty);
+
+        cache.flush();
+        assert!(!cache.lock().unwrap().dirty);
+        drop(cache);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn eviction_keeps_every_key_of_the_stored_replay() {
+        let dir = temp_dir("evict");
+        let cache = ReplayCache::new(dir.clone(), 10, default_keys);
+        let ids = ReplayIds {
+            match_id: Some("42".to_string()),
+            toon: Some("alice".to_string()),
+            replay_id: Some("7".to_string()),
+        };
+        cache
+            .put(
+                "https://cdn.example/a.rep",
+                &ReplayIds::default(),
+                "a.rep",
+                b"first replay",
+            )
+            .unwrap();
+        cache
+            .put("https://cdn.example/b.rep", &ids, "b.rep", b"second replay")
+            .unwrap();
+
+        let keys: Vec<String> = cache
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(keys, ["https://cdn.example/b.rep", "match:42:alice:7"]);
+        assert_eq!(cache.stats().unwrap().evictions, 1);
+        drop(cache);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn replays_are_found_by_their_match_from_another_url() {
+        let dir = temp_dir("match");
+        let cache = ReplayCache::new(dir.clone(), DEFAULT_MAX_BYTES, default_keys);
+        let ids = ReplayIds {
+            match_id: Some("42".to_string()),
+            toon: Some("alice".to_string()),
+            replay_id: Some("7".to_string()),
+        };
+        cache
+            .put(
+                "https://cdn-1.example/a/r.rep?sig=1",
+                &ids,
+                "r.rep",
+                b"replay",
+            )
+            .unwrap();
+
+        let other_url = "https://cdn-2.example/b/r.rep?sig=2";
+        assert_eq!(cache.get(other_url, &ReplayIds::default()), None);
+        assert_eq!(cache.get(other_url, &ids).as_deref(), Some(&b"replay"[..]));
+        // The hit links the new URL too
+        assert_eq!(
+            cache.get(other_url, &ReplayIds::default()).as_deref(),
+            Some(&b"replay"[..])
+        );
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        drop(cache);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn normalize_url_drops_volatile_parts() {
+        assert_eq!(
+            normalize_url("https://cdn.example/r.rep?b=2&Expires=1&a=1#top"),
+            "https://cdn.example/r.rep?a=1&b=2"
+        );
+        assert_eq!(
+            normalize_url("https://cdn.example/r.rep?X-Amz-Signature=abc&X-Amz-Date=1"),
+            "https://cdn.example/r.rep"
+        );
+        assert_eq!(
+            normalize_url("https://cdn.example/r.rep"),
+            "https://cdn.example/r.rep"
+        );
+        assert_eq!(
+            normalize_url("https://cdn.example/r.rep?&=x"),
+            "https://cdn.example/r.rep"
+        );
+    }
+
+    #[test]
+    fn default_keys_need_every_id_for_the_match_key() {
+        let url = "https://cdn.example/r.rep?Expires=1";
+        let ids = ReplayIds {
+            match_id: Some("42".to_string()),
+            toon: Some("alice".to_string()),
+            replay_id: Some("7".to_string()),
+        };
+        assert_eq!(
+            default_keys(url, &ids),
+            ["match:42:alice:7", "https://cdn.example/r.rep"]
+        );
+
+        let partial = ReplayIds {
+            replay_id: None,
+            ..ids
+        };
+        assert_eq!(default_keys(url, &partial), ["https://cdn.example/r.rep"]);
+        assert_eq!(
+            default_keys(url, &ReplayIds::default()),
+            ["https://cdn.example/r.rep"]
+        );
     }
 }
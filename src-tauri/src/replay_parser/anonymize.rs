@@ -0,0 +1,100 @@
+use crate::replay_parser::{
+    extended,
+    frames::{Commands, FRAME_HEADER_SIZE},
+    writer, Command, ParseError, ParsedReplay,
+};
+
+/// Chat messages are stored in a fixed, null-padded field at the end of the
+/// command
+const CHAT_MESSAGE_SIZE: usize = 80;
+
+/// Placeholders written over identifying data
+#[derive(Debug, Clone)]
+pub struct AnonymizeOptions {
+    /// Name given to each player; `{n}` becomes the player's 1-based number
+    pub player_name: String,
+    pub host_name: String,
+    /// The game's name, which often carries the creator's name or clan tag
+    pub title: String,
+    /// Text that replaces every chat message
+    pub chat_message: String,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        Self {
+            player_name: "Player {n}".to_string(),
+            host_name: "Host".to_string(),
+            title: "Game".to_string(),
+            chat_message: "[removed]".to_string(),
+        }
+    }
+}
+
+/// Writes a copy of `replay` with player names, the host name, the game
+/// title and chat replaced by placeholders, and ShieldBattery's user and
+/// game ids zeroed. Every other command is copied unchanged.
+pub fn anonymize(
+    mut replay: ParsedReplay,
+    options: &AnonymizeOptions,
+) -> Result<Vec<u8>, ParseError> {
+    let info = &mut replay.game_info;
+    info.host_name = options.host_name.clone();
+    info.title = options.title.clone();
+    let named = info
+        .player_structs
+        .iter_mut()
+        .filter(|p| !p.name.is_empty());
+    for (index, player) in named.enumerate() {
+        player.name = options.player_name.replace("{n}", &(index + 1).to_string());
+    }
+
+    for section in &mut replay.extended.raw {
+        if &section.tag == b"Sbat" {
+            extended::scrub_shieldbattery(&mut section.data);
+        }
+    }
+    if let Some(sbat) = &mut replay.extended.shieldbattery {
+        sbat.game_id = [0; 16];
+        sbat.user_ids = [0; 8];
+    }
+
+    let mut commands = replay.commands.decompress(&mut Vec::new())?;
+    scrub_chat(&mut commands, &options.chat_message);
+
+    Ok(writer::write_replay_with_commands(&replay, &commands))
+}
+
+/// Overwrites the message of every chat command in a decompressed commands
+/// section. Message lengths are fixed, so nothing else moves.
+fn scrub_chat(data: &mut [u8], placeholder: &str) {
+    let mut replacement = [0u8; CHAT_MESSAGE_SIZE];
+    let mut len = placeholder.len().min(CHAT_MESSAGE_SIZE - 1);
+    while !placeholder.is_char_boundary(len) {
+        len -= 1;
+    }
+    replacement[..len].copy_from_slice(&placeholder.as_bytes()[..len]);
+
+    let mut pos = 0;
+    while let Some(header) = data.get(pos..pos + FRAME_HEADER_SIZE) {
+        let start = pos + FRAME_HEADER_SIZE;
+        let end = start + header[4] as usize;
+        if end > data.len() {
+            break;
+        }
+
+        let mut messages = Vec::new();
+        let mut commands = Commands::new(&data[start..end]);
+        while let Some(command) = commands.next() {
+            if let Command::Chat { .. } = command.command {
+                let command_end = end - commands.remaining().len();
+                messages.push(command_end - CHAT_MESSAGE_SIZE);
+            }
+        }
+        for offset in messages {
+            data[offset..offset + CHAT_MESSAGE_SIZE].copy_from_slice(&replacement);
+        }
+
+        pos = end;
+    }
+}
@@ -0,0 +1,365 @@
+use crate::replay_parser::{Command, FrameClock, HotkeyAction, ParsedReplay, PlayerStruct};
+
+/// A selection replaced this quickly by another one never got used
+const SELECTION_SPAM_FRAMES: u32 = 8;
+/// An identical command repeated this quickly adds nothing
+const REPEAT_FRAMES: u32 = 10;
+/// A cancel this soon after the previous command undoes a misclick
+const INSTANT_CANCEL_FRAMES: u32 = 20;
+/// Train commands beyond a full queue of 5 are rejected by the game
+const MAX_QUEUE: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct PlayerApm {
+    pub player_id: u8,
+    pub apm: u32,
+    pub eapm: u32,
+    /// Actions counted towards `apm`
+    pub actions: u32,
+    /// Actions counted towards `eapm`
+    pub effective_actions: u32,
+    /// Frame of the player's last command, which ends their measured time
+    pub last_frame: u32,
+    pub timeline: Vec<MinuteApm>,
+}
+
+/// Actions counted within one minute of game time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinuteApm {
+    pub minute: u32,
+    pub apm: u32,
+    pub eapm: u32,
+}
+
+impl Command<'_> {
+    /// Whether this is something a player did in the game, as opposed to
+    /// chat, network bookkeeping or lobby traffic
+    pub fn is_action(&self) -> bool {
+        !matches!(
+            self,
+            Command::KeepAlive
+                | Command::SaveGame { .. }
+                | Command::LoadGame { .. }
+                | Command::RestartGame
+                | Command::GameSpeed(_)
+                | Command::Pause
+                | Command::Resume
+                | Command::Sync(_)
+                | Command::VoiceEnable
+                | Command::VoiceDisable
+                | Command::VoiceSquelch(_)
+                | Command::VoiceUnsquelch(_)
+                | Command::StartGame
+                | Command::DownloadPercentage(_)
+                | Command::ChangeGameSlot(_)
+                | Command::NewNetPlayer(_)
+                | Command::JoinedGame(_)
+                | Command::ChangeRace { .. }
+                | Command::TeamGameTeam(_)
+                | Command::UmsTeam(_)
+                | Command::MeleeTeam { .. }
+                | Command::SwapPlayers { .. }
+                | Command::SavedData(_)
+                | Command::BriefingStart
+                | Command::Latency(_)
+                | Command::ReplaySpeed(_)
+                | Command::LeaveGame { .. }
+                | Command::MinimapPing { .. }
+                | Command::MakeGamePublic
+                | Command::Chat { .. }
+                | Command::Unknown { .. }
+        )
+    }
+
+    fn is_selection(&self) -> bool {
+        matches!(
+            self,
+            Command::Select(_)
+                | Command::ShiftSelect(_)
+                | Command::ShiftDeselect(_)
+                | Command::Select121(_)
+                | Command::ShiftSelect121(_)
+                | Command::ShiftDeselect121(_)
+                | Command::Hotkey {
+                    action: HotkeyAction::Select,
+                    ..
+                }
+        )
+    }
+
+    fn is_cancel(&self) -> bool {
+        matches!(
+            self,
+            Command::CancelBuild
+                | Command::CancelMorph
+                | Command::CancelTrain(_)
+                | Command::CancelResearch
+                | Command::CancelUpgrade
+                | Command::CancelAddon
+                | Command::CancelNuke
+        )
+    }
+}
+
+/// An action as EAPM sees it. Everything that depends on the previous
+/// action is worked out as soon as it is issued, while the bytes of both
+/// are at hand, so nothing of a frame needs to outlive it.
+#[derive(Debug, Clone, Copy)]
+struct Action {
+    frame: u32,
+    selection: bool,
+    cancel: bool,
+    /// How many identical train commands in a row end with this one
+    queued_trains: usize,
+    /// A quick repeat, an instant cancel or a train beyond a full queue
+    wasted: bool,
+}
+
+impl Action {
+    /// Judges `command` against the player's previous action. Commands are
+    /// compared by their bytes, which decode to equal commands exactly when
+    /// they are equal.
+    fn new(prev: Option<(&Action, &[u8])>, frame: u32, command: Command, bytes: &[u8]) -> Self {
+        let same = prev.is_some_and(|(_, prev_bytes)| prev_bytes == bytes);
+        let since_prev = prev.map(|(prev, _)| frame.saturating_sub(prev.frame));
+
+        let queued_trains = match command {
+            Command::Train(_) | Command::TrainFighter if same => {
+                prev.map_or(0, |(prev, _)| prev.queued_trains) + 1
+            }
+            Command::Train(_) | Command::TrainFighter => 1,
+            _ => 0,
+        };
+
+        let repeated = same
+            && match command {
+                // Assigning the same group again never changes anything
+                Command::Hotkey {
+                    action: HotkeyAction::Assign,
+                    ..
+                } => true,
+                _ => since_prev.is_some_and(|frames| frames <= REPEAT_FRAMES),
+            };
+
+        let instant_cancel = command.is_cancel()
+            && prev.is_some_and(|(prev, _)| !prev.cancel)
+            && since_prev.is_some_and(|frames| frames <= INSTANT_CANCEL_FRAMES);
+
+        Self {
+            frame,
+            selection: command.is_selection(),
+            cancel: command.is_cancel(),
+            queued_trains,
+            wasted: repeated || instant_cancel || queued_trains > MAX_QUEUE,
+        }
+    }
+
+    /// Whether the action counts towards EAPM, once the player's next
+    /// action is known
+    fn is_effective(&self, next: Option<&Action>) -> bool {
+        let spammed_selection = self.selection
+            && next.is_some_and(|next| {
+                next.selection && next.frame.saturating_sub(self.frame) <= SELECTION_SPAM_FRAMES
+            });
+        !(self.wasted || spammed_selection)
+    }
+}
+
+fn per_minute(count: usize, duration_ms: u32) -> u32 {
+    if duration_ms == 0 {
+        return 0;
+    }
+    (count as u64 * 60_000 / duration_ms as u64) as u32
+}
+
+/// Counts one player's actions as the frames stream by. An action is
+/// decided once the player's next action is known.
+struct Tally {
+    player_id: u8,
+    current: Option<Action>,
+    /// The bytes of `current`, kept to compare the next action with
+    current_bytes: Vec<u8>,
+    actions: u32,
+    effective_actions: u32,
+    last_frame: u32,
+    timeline: Vec<MinuteApm>,
+}
+
+impl Tally {
+    fn new(player: &PlayerStruct) -> Self {
+        Self {
+            player_id: player.id,
+            current: None,
+            current_bytes: Vec::new(),
+            actions: 0,
+            effective_actions: 0,
+            last_frame: 0,
+            timeline: Vec::new(),
+        }
+    }
+
+    /// Takes the player's next action, given its bytes
+    fn push(&mut self, frame: u32, command: Command, bytes: &[u8], clock: FrameClock) {
+        let prev = self
+            .current
+            .as_ref()
+            .map(|a| (a, self.current_bytes.as_slice()));
+        let next = Action::new(prev, frame, command, bytes);
+        self.decide(Some(&next), clock);
+        self.current = Some(next);
+        self.current_bytes.clear();
+        self.current_bytes.extend_from_slice(bytes);
+    }
+
+    /// Counts the current action, now that the next one is known
+    fn decide(&mut self, next: Option<&Action>, clock: FrameClock) {
+        if let Some(current) = self.current {
+            self.count(current.frame, current.is_effective(next), clock);
+        }
+    }
+
+    fn count(&mut self, frame: u32, effective: bool, clock: FrameClock) {
+        let minute = clock.ms(frame) / 60_000;
+        while self.timeline.len() <= minute as usize {
+            self.timeline.push(MinuteApm {
+                minute: self.timeline.len() as u32,
+                apm: 0,
+                eapm: 0,
+            });
+        }
+        let entry = &mut self.timeline[minute as usize];
+        entry.apm += 1;
+        self.actions += 1;
+        if effective {
+            entry.eapm += 1;
+            self.effective_actions += 1;
+        }
+    }
+
+    fn finish(mut self, replay: &ParsedReplay, clock: FrameClock) -> PlayerApm {
+        self.decide(None, clock);
+        // Players who leave early are measured up to their last command
+        let duration_ms = clock.ms(self.last_frame.min(replay.game_info.frames));
+        PlayerApm {
+            player_id: self.player_id,
+            apm: per_minute(self.actions as usize, duration_ms),
+            eapm: per_minute(self.effective_actions as usize, duration_ms),
+            actions: self.actions,
+            effective_actions: self.effective_actions,
+            last_frame: self.last_frame,
+            timeline: self.timeline,
+        }
+    }
+}
+
+impl ParsedReplay {
+    /// APM and EAPM of every player that is not observing, counted in a
+    /// single pass over the commands
+    pub fn apm(&self) -> Vec<PlayerApm> {
+        let clock = self.clock();
+        let mut tallies: Vec<Tally> = self.players().map(Tally::new).collect();
+        self.read_commands(|frame_number, commands| {
+            while let Some((command, bytes)) = commands.next_with_bytes() {
+                let Some(tally) = tallies
+                    .iter_mut()
+                    .find(|t| t.player_id == command.player_id)
+                else {
+                    continue;
+                };
+                tally.last_frame = frame_number;
+                if command.command.is_action() {
+                    tally.push(frame_number, command.command, bytes, clock);
+                }
+            }
+        });
+        tallies
+            .into_iter()
+            .map(|tally| tally.finish(self, clock))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_parser::Commands;
+
+    /// The EAPM decision for each action, made the way `Tally` makes them.
+    /// Commands are encoded as the replay stores them, for player 0.
+    fn flags(actions: &[(u32, Command)]) -> Vec<bool> {
+        let bytes: Vec<Vec<u8>> = actions.iter().map(|(_, c)| encode(c)).collect();
+        let mut judged: Vec<Action> = Vec::new();
+        for (i, &(frame, command)) in actions.iter().enumerate() {
+            let prev = i.checked_sub(1).map(|p| (&judged[p], bytes[p].as_slice()));
+            let action = Action::new(prev, frame, command, &bytes[i]);
+            judged.push(action);
+        }
+        (0..judged.len())
+            .map(|i| judged[i].is_effective(judged.get(i + 1)))
+            .collect()
+    }
+
+    fn encode(command: &Command) -> Vec<u8> {
+        let mut bytes = vec![0, command.opcode()];
+        match *command {
+            Command::Hotkey { action, group } => {
+                let action = match action {
+                    HotkeyAction::Assign => 0,
+                    HotkeyAction::Select => 1,
+                    HotkeyAction::Add => 2,
+                    HotkeyAction::Unknown(v) => v,
+                };
+                bytes.extend([action, group]);
+            }
+            Command::Stop { queued } => bytes.push(queued.into()),
+            Command::Train(id) | Command::CancelTrain(id) => bytes.extend(id.to_le_bytes()),
+            _ => unimplemented!("not used by the tests"),
+        }
+        assert_eq!(Commands::new(&bytes).next().unwrap().command, *command);
+        bytes
+    }
+
+    fn hotkey(action: HotkeyAction, group: u8) -> Command<'static> {
+        Command::Hotkey { action, group }
+    }
+
+    const STOP: Command = Command::Stop { queued: false };
+
+    #[test]
+    fn selections_replaced_right_away_are_ineffective() {
+        let select = |group| hotkey(HotkeyAction::Select, group);
+        assert_eq!(flags(&[(0, select(1)), (5, select(2))]), [false, true]);
+        assert_eq!(flags(&[(0, select(1)), (20, select(2))]), [true, true]);
+        assert_eq!(flags(&[(0, select(1)), (5, STOP)]), [true, true]);
+    }
+
+    #[test]
+    fn quick_repeats_are_ineffective() {
+        assert_eq!(flags(&[(0, STOP), (5, STOP)]), [true, false]);
+        assert_eq!(flags(&[(0, STOP), (30, STOP)]), [true, true]);
+        // Assigning the same group again never does anything
+        let assign = hotkey(HotkeyAction::Assign, 1);
+        assert_eq!(flags(&[(0, assign), (500, assign)]), [true, false]);
+    }
+
+    #[test]
+    fn instant_cancels_are_ineffective() {
+        let train = Command::Train(7);
+        let cancel = Command::CancelTrain(0);
+        assert_eq!(flags(&[(0, train), (10, cancel)]), [true, false]);
+        assert_eq!(flags(&[(0, train), (50, cancel)]), [true, true]);
+        assert_eq!(flags(&[(0, cancel), (10, cancel)]), [true, false]);
+    }
+
+    #[test]
+    fn trains_beyond_a_full_queue_are_ineffective() {
+        let trains: Vec<(u32, Command)> = (0..7).map(|i| (i * 20, Command::Train(7))).collect();
+        assert_eq!(flags(&trains), [true, true, true, true, true, false, false]);
+
+        // Another command in between starts a new queue
+        let mut mixed = trains[..5].to_vec();
+        mixed.push((100, STOP));
+        mixed.extend(trains[..2].iter().map(|&(f, c)| (f + 120, c)));
+        assert!(flags(&mixed).iter().all(|&e| e));
+    }
+}
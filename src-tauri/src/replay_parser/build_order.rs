@@ -0,0 +1,96 @@
+use crate::replay_parser::{names, Command, ParsedReplay};
+
+/// Build order used when a Terran building lifts off and lands again
+const ORDER_BUILDING_LAND: u8 = 0x47;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildOrderKind {
+    Building,
+    Unit,
+    Morph,
+    Research,
+    Upgrade,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOrderItem {
+    pub frame: u32,
+    pub timestamp_ms: u32,
+    pub kind: BuildOrderKind,
+    /// Unit, tech or upgrade id, depending on `kind`
+    pub id: u16,
+    pub name: String,
+}
+
+impl Command<'_> {
+    /// What this command adds to a build order, if anything
+    fn build_order_entry(&self) -> Option<(BuildOrderKind, u16, Option<&'static str>)> {
+        match *self {
+            Command::Build {
+                order, unit_type, ..
+            } if order != ORDER_BUILDING_LAND => Some((
+                BuildOrderKind::Building,
+                unit_type,
+                names::unit_name(unit_type),
+            )),
+            Command::Train(unit_type) => {
+                Some((BuildOrderKind::Unit, unit_type, names::unit_name(unit_type)))
+            }
+            Command::UnitMorph(unit_type) | Command::BuildingMorph(unit_type) => Some((
+                BuildOrderKind::Morph,
+                unit_type,
+                names::unit_name(unit_type),
+            )),
+            Command::Research(tech) => Some((
+                BuildOrderKind::Research,
+                tech as u16,
+                names::tech_name(tech),
+            )),
+            Command::Upgrade(upgrade) => Some((
+                BuildOrderKind::Upgrade,
+                upgrade as u16,
+                names::upgrade_name(upgrade),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// One player's build order
+#[derive(Debug, Clone)]
+pub struct PlayerBuildOrder {
+    pub player_id: u8,
+    pub items: Vec<BuildOrderItem>,
+}
+
+impl ParsedReplay {
+    /// Everything each player that is not observing ordered to be built,
+    /// trained, morphed, researched or upgraded, in the order it was issued.
+    /// Cancelled orders are kept. Reads the commands once for all players.
+    pub fn build_orders(&self) -> Vec<PlayerBuildOrder> {
+        let clock = self.clock();
+        let mut orders: Vec<PlayerBuildOrder> = self
+            .players()
+            .map(|player| PlayerBuildOrder {
+                player_id: player.id,
+                items: Vec::new(),
+            })
+            .collect();
+        self.each_command(|frame, command| {
+            let Some(order) = orders.iter_mut().find(|o| o.player_id == command.player_id) else {
+                return;
+            };
+            let Some((kind, id, name)) = command.command.build_order_entry() else {
+                return;
+            };
+            order.items.push(BuildOrderItem {
+                frame,
+                timestamp_ms: clock.ms(frame),
+                kind,
+                id,
+                name: name.map_or_else(|| format!("Unknown ({id})"), str::to_string),
+            });
+        });
+        orders
+    }
+}
@@ -0,0 +1,316 @@
+use crate::replay_parser::{
+    section::{Section, SectionReader},
+    text, ParseError,
+};
+
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_u16, le_u32},
+};
+
+/// Unit id of the "Start Location" marker placed in UNIT
+const START_LOCATION_UNIT_ID: u16 = 214;
+/// Size of one entry of the UNIT section
+const UNIT_ENTRY_SIZE: usize = 36;
+
+/// Decoded scenario (CHK) data embedded in the replay
+#[derive(Debug, Default)]
+pub struct Chk {
+    pub version: u16,
+    pub width: u16,
+    pub height: u16,
+    pub tileset: Tileset,
+    /// Slot owner types from OWNR, one per slot
+    pub owners: Vec<u8>,
+    /// Slot races from SIDE, one per slot
+    pub sides: Vec<u8>,
+    pub start_locations: Vec<StartLocation>,
+    pub strings: Vec<String>,
+    /// Tile ids from MTXM, row by row
+    pub tiles: Vec<u16>,
+    pub scenario_name: String,
+    pub scenario_description: String,
+    /// The CHK data as embedded in the replay
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartLocation {
+    pub owner: u8,
+    /// Position in pixels
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tileset {
+    #[default]
+    Badlands,
+    SpacePlatform,
+    Installation,
+    Ashworld,
+    Jungle,
+    Desert,
+    Arctic,
+    Twilight,
+}
+
+impl From<u16> for Tileset {
+    fn from(value: u16) -> Self {
+        // Only the low 3 bits are used by the game
+        match value & 0x7 {
+            0 => Tileset::Badlands,
+            1 => Tileset::SpacePlatform,
+            2 => Tileset::Installation,
+            3 => Tileset::Ashworld,
+            4 => Tileset::Jungle,
+            5 => Tileset::Desert,
+            6 => Tileset::Arctic,
+            _ => Tileset::Twilight,
+        }
+    }
+}
+
+impl Chk {
+    /// Looks up a 1-based string id as used by SPRP and other sections
+    pub fn string(&self, id: u16) -> Option<&str> {
+        let index = (id as usize).checked_sub(1)?;
+        self.strings.get(index).map(String::as_str)
+    }
+}
+
+pub fn parse_map_section<'a>(
+    input: &'a [u8],
+    reader: SectionReader,
+    warnings: &mut Vec<ParseError>,
+) -> Result<(&'a [u8], Chk), ParseError> {
+    let start = input;
+    let (input, size) = reader.read_size(input, Section::MapSize, warnings)?;
+    let offset = start.len() - input.len();
+    let (input, data) = reader
+        .read(input, size as usize, Section::Map, warnings)
+        .map_err(|e| e.offset_by(offset))?;
+
+    let mut chk = parse_chk(&data).map_err(|e| e.in_section(Section::Map))?;
+    chk.raw = data;
+
+    Ok((input, chk))
+}
+
+/// Walks the CHK sections. Later copies of a section override earlier ones,
+/// like the game does, and a section running past the end is truncated.
+pub fn parse_chk(data: &[u8]) -> Result<Chk, ParseError> {
+    let mut chk = Chk::default();
+    let mut scenario_props = None;
+
+    let mut input = data;
+    while input.len() >= 8 {
+        let (rest, name) = take(4usize)(input)?;
+        let (rest, size) = le_u32(rest)?;
+        let size = (size as usize).min(rest.len());
+        let (rest, body) = take(size)(rest)?;
+        input = rest;
+
+        match name {
+            b"VER " => chk.version = le_u16(body)?.1,
+            b"DIM " => {
+                let (body, width) = le_u16(body)?;
+                let (_, height) = le_u16(body)?;
+                chk.width = width;
+                chk.height = height;
+            }
+            b"ERA " => chk.tileset = le_u16(body)?.1.into(),
+            b"OWNR" => chk.owners = body.to_vec(),
+            b"SIDE" => chk.sides = body.to_vec(),
+            b"UNIT" => chk.start_locations = parse_start_locations(body),
+            b"STR " => chk.strings = parse_strings(body, false)?,
+            b"STRx" => chk.strings = parse_strings(body, true)?,
+            b"MTXM" => {
+                chk.tiles = body
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect()
+            }
+            b"SPRP" => {
+                let (body, name) = le_u16(body)?;
+                let (_, description) = le_u16(body)?;
+                scenario_props = Some((name, description));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, description)) = scenario_props {
+        chk.scenario_name = chk.string(name).unwrap_or_default().to_string();
+        chk.scenario_description = chk.string(description).unwrap_or_default().to_string();
+    }
+
+    Ok(chk)
+}
+
+fn parse_start_locations(body: &[u8]) -> Vec<StartLocation> {
+    body.chunks_exact(UNIT_ENTRY_SIZE)
+        .filter_map(|unit| {
+            let x = u16::from_le_bytes([unit[4], unit[5]]);
+            let y = u16::from_le_bytes([unit[6], unit[7]]);
+            let unit_id = u16::from_le_bytes([unit[8], unit[9]]);
+            let owner = unit[16];
+            (unit_id == START_LOCATION_UNIT_ID).then_some(StartLocation { owner, x, y })
+        })
+        .collect()
+}
+
+/// STR uses 16-bit counts and offsets, STRx (SC:R) 32-bit ones. Offsets are
+/// relative to the start of the section.
+fn parse_strings(body: &[u8], extended: bool) -> Result<Vec<String>, ParseError> {
+    let (mut input, count) = if extended {
+        le_u32(body)?
+    } else {
+        let (input, count) = le_u16(body)?;
+        (input, count as u32)
+    };
+
+    // The count is not trusted; each string takes at least a 2 byte offset
+    let mut strings = Vec::with_capacity((count as usize).min(body.len() / 2));
+    for _ in 0..count {
+        let offset = if extended {
+            let (rest, offset) = le_u32(input)?;
+            input = rest;
+            offset as usize
+        } else {
+            let (rest, offset) = le_u16(input)?;
+            input = rest;
+            offset as usize
+        };
+
+        let bytes = body.get(offset..).unwrap_or_default();
+        strings.push(text::decode(bytes));
+    }
+
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        [&name[..], &(body.len() as u32).to_le_bytes(), body].concat()
+    }
+
+    fn unit(id: u16, owner: u8, x: u16, y: u16) -> Vec<u8> {
+        let mut unit = vec![0; UNIT_ENTRY_SIZE];
+        unit[4..6].copy_from_slice(&x.to_le_bytes());
+        unit[6..8].copy_from_slice(&y.to_le_bytes());
+        unit[8..10].copy_from_slice(&id.to_le_bytes());
+        unit[16] = owner;
+        unit
+    }
+
+    #[test]
+    fn str_uses_16_bit_offsets() {
+        let body = [&[2, 0, 6, 0, 10, 0][..], b"Map\0Desc\0"].concat();
+        let chk = parse_chk(&section(b"STR ", &body)).unwrap();
+        assert_eq!(chk.strings, ["Map", "Desc"]);
+        assert_eq!(chk.string(2), Some("Desc"));
+        assert_eq!(chk.string(0), None);
+    }
+
+    #[test]
+    fn strx_uses_32_bit_offsets() {
+        let body = [&[1, 0, 0, 0, 8, 0, 0, 0][..], b"Map\0"].concat();
+        let chk = parse_chk(&section(b"STRx", &body)).unwrap();
+        assert_eq!(chk.strings, ["Map"]);
+    }
+
+    #[test]
+    fn scenario_name_is_looked_up_in_the_strings() {
+        let data = [
+            section(b"SPRP", &[1, 0, 2, 0]),
+            section(
+                b"STR ",
+                &[&[2, 0, 6, 0, 10, 0][..], b"Map\0Desc\0"].concat(),
+            ),
+        ]
+        .concat();
+        let chk = parse_chk(&data).unwrap();
+        assert_eq!(chk.scenario_name, "Map");
+        assert_eq!(chk.scenario_description, "Desc");
+    }
+
+    #[test]
+    fn dimensions_tileset_owners_and_start_locations() {
+        let units = [
+            unit(START_LOCATION_UNIT_ID, 0, 100, 200),
+            unit(0, 1, 5, 5), // a marine
+            unit(START_LOCATION_UNIT_ID, 1, 300, 400),
+        ]
+        .concat();
+        let data = [
+            section(b"DIM ", &[128, 0, 96, 0]),
+            section(b"ERA ", &[0x0c, 0]),
+            section(b"OWNR", &[6, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            section(b"UNIT", &units),
+        ]
+        .concat();
+        let chk = parse_chk(&data).unwrap();
+        assert_eq!((chk.width, chk.height), (128, 96));
+        // Only the low 3 bits count
+        assert_eq!(chk.tileset, Tileset::Jungle);
+        assert_eq!(chk.owners[..2], [6, 6]);
+        assert_eq!(
+            chk.start_locations,
+            [
+                StartLocation {
+                    owner: 0,
+                    x: 100,
+                    y: 200
+                },
+                StartLocation {
+                    owner: 1,
+                    x: 300,
+                    y: 400
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn later_sections_override_earlier_ones() {
+        let data = [
+            section(b"DIM ", &[64, 0, 64, 0]),
+            section(b"DIM ", &[128, 0, 128, 0]),
+        ]
+        .concat();
+        assert_eq!(parse_chk(&data).unwrap().width, 128);
+    }
+
+    #[test]
+    fn a_section_running_past_the_end_is_truncated() {
+        let mut data = section(b"DIM ", &[128, 0, 96, 0]);
+        data.extend(b"OWNR");
+        data.extend(100u32.to_le_bytes());
+        data.extend([6, 6]);
+        let chk = parse_chk(&data).unwrap();
+        assert_eq!(chk.width, 128);
+        assert_eq!(chk.owners, [6, 6]);
+
+        // Partial unit entries are dropped
+        let units = unit(START_LOCATION_UNIT_ID, 0, 100, 200);
+        let chk = parse_chk(&section(b"UNIT", &units[..20])).unwrap();
+        assert!(chk.start_locations.is_empty());
+    }
+
+    #[test]
+    fn truncated_bodies_are_errors() {
+        assert!(parse_chk(&section(b"DIM ", &[128, 0, 96])).is_err());
+        // Two strings claimed, one offset present
+        assert!(parse_chk(&section(b"STR ", &[2, 0, 4, 0])).is_err());
+    }
+
+    #[test]
+    fn huge_string_counts_do_not_allocate_up_front() {
+        let body = [0xff, 0xff, 0xff, 0xff];
+        assert!(parse_chk(&section(b"STRx", &body)).is_err());
+    }
+}
@@ -1,39 +1,151 @@
+use crate::replay_parser::section::Section;
+
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseError {
-    NomError(nom::Err<nom::error::Error<Vec<u8>>>),
+    /// The data ended before a complete value could be read
+    Truncated(Location),
     UnsupportedVersion(String),
-    InvalidData(String),
+    InvalidData {
+        location: Location,
+        message: String,
+    },
+    ChecksumMismatch {
+        section: Section,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+/// Where in the replay an error happened. Parsers fill in what they know as
+/// the error travels up, without overwriting more specific details.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Location {
+    pub section: Option<Section>,
+    /// Absolute offset in the replay file
+    pub offset: Option<usize>,
+    /// Offset in the decompressed section data
+    pub data_offset: Option<usize>,
+    pub frame: Option<u32>,
+    pub opcode: Option<u8>,
+}
+
+impl ParseError {
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ParseError::InvalidData {
+            location: Location::default(),
+            message: message.into(),
+        }
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ParseError::Truncated(location) | ParseError::InvalidData { location, .. } => {
+                Some(location)
+            }
+            ParseError::UnsupportedVersion(_) | ParseError::ChecksumMismatch { .. } => None,
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            ParseError::Truncated(location) | ParseError::InvalidData { location, .. } => {
+                Some(location)
+            }
+            ParseError::UnsupportedVersion(_) | ParseError::ChecksumMismatch { .. } => None,
+        }
+    }
+
+    pub fn in_section(mut self, section: Section) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.section.get_or_insert(section);
+        }
+        self
+    }
+
+    /// Moves a file offset that is relative to `base` to an absolute one.
+    /// Errors without an offset are placed at `base`.
+    pub fn offset_by(mut self, base: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.offset = Some(base + location.offset.unwrap_or(0));
+        }
+        self
+    }
+
+    pub fn at_data_offset(mut self, offset: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.data_offset.get_or_insert(offset);
+        }
+        self
+    }
+
+    pub fn at_frame(mut self, frame: u32) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.frame.get_or_insert(frame);
+        }
+        self
+    }
+
+    pub fn with_opcode(mut self, opcode: u8) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.opcode.get_or_insert(opcode);
+        }
+        self
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::NomError(e) => write!(f, "Parse error: {e:?}"),
+            ParseError::Truncated(location) => write!(f, "Replay truncated{location}"),
             ParseError::UnsupportedVersion(v) => write!(f, "Unsupported replay version: {v}"),
-            ParseError::InvalidData(msg) => write!(f, "Invalid data: {msg}"),
+            ParseError::InvalidData { location, message } => {
+                write!(f, "Invalid data{location}: {message}")
+            }
+            ParseError::ChecksumMismatch {
+                section,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch in {section} section: expected {expected:08x}, got {actual:08x}"
+            ),
+        }
+    }
+}
+
+/// Written as a suffix, e.g. " at frame 12345 in commands section"
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(frame) = self.frame {
+            write!(f, " at frame {frame}")?;
+        }
+        if let Some(opcode) = self.opcode {
+            write!(f, " in command 0x{opcode:02x}")?;
+        }
+        if let Some(section) = self.section {
+            write!(f, " in {section} section")?;
+        }
+        match (self.offset, self.data_offset) {
+            (_, Some(offset)) => write!(f, " (byte {offset} of the decompressed data)"),
+            (Some(offset), None) => write!(f, " (byte {offset})"),
+            (None, None) => Ok(()),
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
-impl<T> From<nom::Err<nom::error::Error<T>>> for ParseError
-where
-    T: Into<Vec<u8>>,
-{
+/// All parsers read fixed-size values with `complete` combinators, so nom
+/// only fails when the input runs out
+impl<T> From<nom::Err<nom::error::Error<T>>> for ParseError {
     fn from(err: nom::Err<nom::error::Error<T>>) -> Self {
         match err {
-            nom::Err::Error(e) => ParseError::NomError(nom::Err::Error(nom::error::Error {
-                input: e.input.into(),
-                code: e.code,
-            })),
-            nom::Err::Failure(e) => ParseError::NomError(nom::Err::Failure(nom::error::Error {
-                input: e.input.into(),
-                code: e.code,
-            })),
-            nom::Err::Incomplete(n) => ParseError::NomError(nom::Err::Incomplete(n)),
+            nom::Err::Error(e) | nom::Err::Failure(e) if e.code != nom::error::ErrorKind::Eof => {
+                ParseError::invalid(format!("{:?}", e.code))
+            }
+            _ => ParseError::Truncated(Location::default()),
         }
     }
 }
@@ -0,0 +1,227 @@
+use crate::replay_parser::{Command, GameSpeed, LeaveReason, ParsedReplay};
+
+/// Slots an alliance or vision mask can refer to
+const SLOT_COUNT: u16 = 12;
+
+/// Something that changed the state of the game, as opposed to a unit order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameEvent {
+    pub frame: u32,
+    pub timestamp_ms: u32,
+    pub player_id: u8,
+    pub kind: GameEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEventKind {
+    /// `resume` is `None` if the game was never resumed
+    Pause {
+        resume: Option<Resume>,
+    },
+    SpeedChange(GameSpeed),
+    /// Slots the player is now allied with
+    Alliance {
+        slots: Vec<u16>,
+        allied_victory: bool,
+    },
+    /// Slots the player now shares vision with
+    Vision {
+        slots: Vec<u16>,
+    },
+    Leave(LeaveReason),
+}
+
+/// The end of a pause. The span from the pause to `frame` is in game
+/// frames, which stop while the game is paused, so how long the players
+/// waited in real time is not in the replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resume {
+    pub player_id: u8,
+    pub frame: u32,
+}
+
+impl ParsedReplay {
+    /// Pauses, speed, alliance and vision changes and leaves, in the order
+    /// they happened
+    pub fn game_events(&self) -> Vec<GameEvent> {
+        let clock = self.clock();
+        let mut events: Vec<GameEvent> = Vec::new();
+        // Index in `events` of the pause waiting for a resume
+        let mut open_pause: Option<usize> = None;
+
+        self.each_command(|frame_number, command| {
+            let kind = match command.command {
+                Command::Pause => {
+                    if open_pause.is_some() {
+                        // already paused
+                        return;
+                    }
+                    open_pause = Some(events.len());
+                    GameEventKind::Pause { resume: None }
+                }
+                Command::Resume => {
+                    if let Some(index) = open_pause.take() {
+                        events[index].kind = GameEventKind::Pause {
+                            resume: Some(Resume {
+                                player_id: command.player_id,
+                                frame: frame_number,
+                            }),
+                        };
+                    }
+                    return;
+                }
+                Command::GameSpeed(speed) => GameEventKind::SpeedChange(speed.into()),
+                Command::Alliance(mask) => {
+                    // Two bits per slot: 1 allied, 2 allied with shared victory
+                    let slot_state = |slot: u16| (mask >> (slot * 2)) & 0b11;
+                    GameEventKind::Alliance {
+                        slots: (0..SLOT_COUNT).filter(|&s| slot_state(s) != 0).collect(),
+                        allied_victory: (0..SLOT_COUNT).any(|s| slot_state(s) == 2),
+                    }
+                }
+                Command::Vision(mask) => GameEventKind::Vision {
+                    slots: (0..SLOT_COUNT).filter(|&s| mask & (1 << s) != 0).collect(),
+                },
+                Command::LeaveGame { reason } => GameEventKind::Leave(reason.into()),
+                _ => return,
+            };
+
+            events.push(GameEvent {
+                frame: frame_number,
+                timestamp_ms: clock.ms(frame_number),
+                player_id: command.player_id,
+                kind,
+            });
+        });
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_parser::tests::{replay, PROTOSS, ZERG};
+
+    fn events(frames: &[(u32, Vec<u8>)]) -> Vec<(u32, u8, GameEventKind)> {
+        replay(&[(PROTOSS, 1), (ZERG, 2)], frames)
+            .game_events()
+            .into_iter()
+            .map(|e| (e.frame, e.player_id, e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn pauses_are_paired_with_the_next_resume() {
+        let events = events(&[
+            (100, vec![0, 0x10]),
+            // pausing again while paused is not a new pause
+            (101, vec![1, 0x10]),
+            (102, vec![1, 0x11]),
+            // resuming an unpaused game is ignored
+            (150, vec![0, 0x11]),
+            (200, vec![1, 0x10]),
+        ]);
+        assert_eq!(
+            events,
+            [
+                (
+                    100,
+                    0,
+                    GameEventKind::Pause {
+                        resume: Some(Resume {
+                            player_id: 1,
+                            frame: 102
+                        })
+                    }
+                ),
+                (200, 1, GameEventKind::Pause { resume: None }),
+            ]
+        );
+    }
+
+    #[test]
+    fn speed_changes() {
+        let events = events(&[(50, vec![0, 0x0f, 3]), (60, vec![1, 0x0f, 9])]);
+        assert_eq!(
+            events,
+            [
+                (50, 0, GameEventKind::SpeedChange(GameSpeed::Normal)),
+                (60, 1, GameEventKind::SpeedChange(GameSpeed::Unknown(9))),
+            ]
+        );
+    }
+
+    #[test]
+    fn alliances_have_two_bits_per_slot() {
+        let alliance = |mask: u32| {
+            let mut block = vec![0, 0x0e];
+            block.extend(mask.to_le_bytes());
+            block
+        };
+        let events = events(&[
+            // allied with slots 0 and 1
+            (10, alliance(0b0101)),
+            // allied with slot 0, and with slot 3 for victory
+            (20, alliance(0b1000_0001)),
+            (30, alliance(0)),
+        ]);
+        assert_eq!(
+            events,
+            [
+                (
+                    10,
+                    0,
+                    GameEventKind::Alliance {
+                        slots: vec![0, 1],
+                        allied_victory: false
+                    }
+                ),
+                (
+                    20,
+                    0,
+                    GameEventKind::Alliance {
+                        slots: vec![0, 3],
+                        allied_victory: true
+                    }
+                ),
+                (
+                    30,
+                    0,
+                    GameEventKind::Alliance {
+                        slots: vec![],
+                        allied_victory: false
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn vision_has_one_bit_per_slot() {
+        let mut block = vec![1, 0x0d];
+        block.extend(0b1000_0000_0110u16.to_le_bytes());
+        assert_eq!(
+            events(&[(10, block)]),
+            [(
+                10,
+                1,
+                GameEventKind::Vision {
+                    slots: vec![1, 2, 11]
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn leaves() {
+        let events = events(&[(300, vec![1, 0x57, 1]), (400, vec![0, 0x57, 6])]);
+        assert_eq!(
+            events,
+            [
+                (300, 1, GameEventKind::Leave(LeaveReason::Quit)),
+                (400, 0, GameEventKind::Leave(LeaveReason::Dropped)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::replay_parser::{
+    section::{ParseOptions, Section, SectionReader},
+    ParseError,
+};
+
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_u16, le_u32},
+};
+use std::ops::Range;
+
+/// Sections SC:R appends after the map data. Each one is a 4 byte tag and a
+/// size, followed by a regular compressed section.
+#[derive(Debug, Default)]
+pub struct ExtendedSections {
+    pub skins: Option<Skins>,
+    pub limits: Option<Limits>,
+    pub bug_fixes: Option<BugFixes>,
+    pub custom_colors: Option<CustomColors>,
+    pub game_config: Option<GameConfig>,
+    pub shieldbattery: Option<ShieldBattery>,
+    /// Every section in file order, including the ones decoded above
+    pub raw: Vec<RawSection>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RawSection {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// SKIN: unit skins picked by each player. The layout is not documented, so
+/// it is kept as-is.
+#[derive(Debug, Clone)]
+pub struct Skins {
+    pub data: Vec<u8>,
+}
+
+/// LMTS: engine object limits the game was played with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub images: u32,
+    pub sprites: u32,
+    pub lone_sprites: u32,
+    pub units: u32,
+    pub bullets: u32,
+    pub orders: u32,
+    pub fog_sprites: u32,
+}
+
+/// BFIX: bit flags of the engine bug fixes enabled for the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BugFixes {
+    pub flags: u64,
+}
+
+/// CCLR: custom colors, one 24 byte entry per playable slot
+#[derive(Debug, Clone)]
+pub struct CustomColors {
+    pub entries: Vec<[u8; 24]>,
+}
+
+/// GCFG: lobby game configuration. The layout is not documented, so it is
+/// kept as-is.
+#[derive(Debug, Clone)]
+pub struct GameConfig {
+    pub data: Vec<u8>,
+}
+
+/// "Sbat": written by ShieldBattery
+#[derive(Debug, Clone)]
+pub struct ShieldBattery {
+    pub format_version: u16,
+    pub starcraft_exe_build: u32,
+    pub shieldbattery_version: String,
+    pub team_game_main_players: [u8; 4],
+    pub starting_races: [u8; 12],
+    pub game_id: [u8; 16],
+    pub user_ids: [u32; 8],
+    /// Only present from format version 1
+    pub game_logic_version: Option<u32>,
+}
+
+impl ExtendedSections {
+    /// Sections with a tag we do not decode
+    pub fn unknown(&self) -> impl Iterator<Item = &RawSection> {
+        self.raw.iter().filter(|s| {
+            !matches!(
+                &s.tag,
+                b"SKIN" | b"LMTS" | b"BFIX" | b"CCLR" | b"GCFG" | b"Sbat"
+            )
+        })
+    }
+}
+
+/// Decompressed size of each known section, used to tell stored chunks apart
+/// from compressed ones
+pub fn known_size(tag: &[u8; 4]) -> Option<usize> {
+    match tag {
+        b"SKIN" => Some(0x15e0),
+        b"LMTS" => Some(0x1c),
+        b"BFIX" => Some(0x08),
+        b"CCLR" => Some(0xc0),
+        b"GCFG" => Some(0x19),
+        _ => None,
+    }
+}
+
+/// In lenient mode a section that fails to read is left out and the error
+/// added to `warnings`, with offsets relative to `input`
+pub fn parse_extended_sections<'a>(
+    input: &'a [u8],
+    reader: SectionReader,
+    warnings: &mut Vec<ParseError>,
+) -> Result<(&'a [u8], ExtendedSections), ParseError> {
+    let mut sections = ExtendedSections::default();
+    // Stored chunks are told apart by the rules in `decompress_chunk`, so a
+    // chunk that does not decompress is damaged, never uncompressed data
+    let reader = SectionReader {
+        options: ParseOptions {
+            strict: true,
+            ..reader.options
+        },
+        ..reader
+    };
+
+    let start = input;
+    let mut input = input;
+    while input.len() >= 8 {
+        let offset = start.len() - input.len();
+        let (rest, tag) = take(4usize)(input)?;
+        let (rest, size) = le_u32(rest)?;
+        if rest.len() < size as usize {
+            // trailing data that is not a complete section
+            break;
+        }
+        let (rest, body) = take(size)(rest)?;
+        input = rest;
+
+        let tag: [u8; 4] = tag.try_into().expect("took 4 bytes");
+        let size = known_size(&tag).unwrap_or(usize::MAX);
+        // the section itself starts after the tag and size
+        let data = match reader.read(body, size, Section::Extended(tag), warnings) {
+            Ok((_, data)) => data,
+            Err(e) if reader.options.lenient => {
+                warnings.push(e.offset_by(offset + 8));
+                continue;
+            }
+            Err(e) => return Err(e.offset_by(offset + 8)),
+        };
+
+        match &tag {
+            b"SKIN" => sections.skins = Some(Skins { data: data.clone() }),
+            b"LMTS" => sections.limits = parse_limits(&data).ok(),
+            b"BFIX" => sections.bug_fixes = parse_bug_fixes(&data).ok(),
+            b"CCLR" => sections.custom_colors = Some(parse_custom_colors(&data)),
+            b"GCFG" => sections.game_config = Some(GameConfig { data: data.clone() }),
+            b"Sbat" => sections.shieldbattery = parse_shieldbattery(&data).ok(),
+            _ => {}
+        }
+
+        sections.raw.push(RawSection { tag, data });
+    }
+
+    Ok((input, sections))
+}
+
+fn parse_limits(data: &[u8]) -> Result<Limits, ParseError> {
+    let (data, images) = le_u32(data)?;
+    let (data, sprites) = le_u32(data)?;
+    let (data, lone_sprites) = le_u32(data)?;
+    let (data, units) = le_u32(data)?;
+    let (data, bullets) = le_u32(data)?;
+    let (data, orders) = le_u32(data)?;
+    let (_, fog_sprites) = le_u32(data)?;
+
+    Ok(Limits {
+        images,
+        sprites,
+        lone_sprites,
+        units,
+        bullets,
+        orders,
+        fog_sprites,
+    })
+}
+
+fn parse_bug_fixes(data: &[u8]) -> Result<BugFixes, ParseError> {
+    let (_, flags) = nom::number::complete::le_u64(data)?;
+    Ok(BugFixes { flags })
+}
+
+fn parse_custom_colors(data: &[u8]) -> CustomColors {
+    let entries = data
+        .chunks_exact(24)
+        .map(|c| c.try_into().expect("chunks of 24"))
+        .collect();
+    CustomColors { entries }
+}
+
+/// Where the game id and the user ids sit in Sbat data
+const SBAT_GAME_ID: Range<usize> = 38..54;
+const SBAT_USER_IDS: Range<usize> = 54..86;
+
+/// Zeroes the ids in Sbat data that lead back to the players: their
+/// ShieldBattery user ids, and the game id, which links to the match page
+pub fn scrub_shieldbattery(data: &mut [u8]) {
+    for range in [SBAT_GAME_ID, SBAT_USER_IDS] {
+        let end = range.end.min(data.len());
+        if let Some(ids) = data.get_mut(range.start..end) {
+            ids.fill(0);
+        }
+    }
+}
+
+fn parse_shieldbattery(data: &[u8]) -> Result<ShieldBattery, ParseError> {
+    let (data, format_version) = le_u16(data)?;
+    let (data, starcraft_exe_build) = le_u32(data)?;
+    let (data, version_bytes) = take(16usize)(data)?;
+    let (data, team_game_main_players) = take(4usize)(data)?;
+    let (data, starting_races) = take(12usize)(data)?;
+    let (data, game_id) = take(16usize)(data)?;
+
+    let mut user_ids = [0u32; 8];
+    let mut data = data;
+    for user_id in user_ids.iter_mut() {
+        let (rest, id) = le_u32(data)?;
+        *user_id = id;
+        data = rest;
+    }
+
+    let game_logic_version = if format_version >= 1 {
+        Some(le_u32(data)?.1)
+    } else {
+        None
+    };
+
+    let end = version_bytes
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(version_bytes.len());
+
+    Ok(ShieldBattery {
+        format_version,
+        starcraft_exe_build,
+        shieldbattery_version: String::from_utf8_lossy(&version_bytes[..end]).to_string(),
+        team_game_main_players: team_game_main_players.try_into().expect("took 4 bytes"),
+        starting_races: starting_races.try_into().expect("took 12 bytes"),
+        game_id: game_id.try_into().expect("took 16 bytes"),
+        user_ids,
+        game_logic_version,
+    })
+}
@@ -1,146 +1,1050 @@
-use crate::replay_parser::ParseError;
+use crate::replay_parser::{
+    error::Location,
+    section::{Chunks, LazySection, Section, SectionReader},
+    ParseError,
+};
 
 use nom::{
-    bytes::complete::take,
-    number::complete::{le_u32, le_u8},
+    bytes::complete::{take, take_till},
+    number::complete::{le_u16, le_u32, le_u8},
 };
 
-#[derive(Debug)]
+/// Frame blocks store their size in a single byte
+const MAX_BLOCK_SIZE: usize = u8::MAX as usize;
+/// Frame number and block size
+pub const FRAME_HEADER_SIZE: usize = 5;
+
+/// The commands issued on one frame. The command block is kept inline and
+/// decoded on demand by `commands`.
+#[derive(Clone)]
 pub struct Frame {
     pub frame_number: u32,
-    pub commands: Vec<Command>,
+    block: [u8; MAX_BLOCK_SIZE],
+    block_len: u8,
+}
+
+impl Frame {
+    /// The undecoded command block
+    pub fn data(&self) -> &[u8] {
+        &self.block[..self.block_len as usize]
+    }
+
+    pub fn commands(&self) -> Commands<'_> {
+        Commands {
+            frame_number: Some(self.frame_number),
+            ..Commands::new(self.data())
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Command {
-    pub command_type: u8,
-    pub data: Vec<u8>,
+impl std::fmt::Debug for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Frame")
+            .field("frame_number", &self.frame_number)
+            .field("commands", &self.commands().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A command together with the id of the player who issued it. The id
+/// matches `PlayerStruct::id`, not the slot id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerCommand<'a> {
+    pub player_id: u8,
+    pub command: Command<'a>,
+}
+
+/// A single decoded player command, borrowing from its frame.
+///
+/// Unit tags, unit types, orders, tech and upgrade ids are kept as the raw
+/// numeric values stored in the replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    KeepAlive,
+    SaveGame {
+        save_info: u32,
+        filename: &'a [u8],
+    },
+    LoadGame {
+        save_info: u32,
+        filename: &'a [u8],
+    },
+    RestartGame,
+    Select(UnitTags<'a>),
+    ShiftSelect(UnitTags<'a>),
+    ShiftDeselect(UnitTags<'a>),
+    Build {
+        order: u8,
+        x: u16,
+        y: u16,
+        unit_type: u16,
+    },
+    Vision(u16),
+    Alliance(u32),
+    GameSpeed(u8),
+    Pause,
+    Resume,
+    Cheat(u32),
+    Hotkey {
+        action: HotkeyAction,
+        group: u8,
+    },
+    RightClick {
+        x: u16,
+        y: u16,
+        target: u16,
+        unit_type: u16,
+        queued: bool,
+    },
+    TargetedOrder {
+        x: u16,
+        y: u16,
+        target: u16,
+        unit_type: u16,
+        order: u8,
+        queued: bool,
+    },
+    CancelBuild,
+    CancelMorph,
+    Stop {
+        queued: bool,
+    },
+    CarrierStop,
+    ReaverStop,
+    OrderNothing,
+    ReturnCargo {
+        queued: bool,
+    },
+    Train(u16),
+    CancelTrain(u16),
+    Cloak {
+        queued: bool,
+    },
+    Decloak {
+        queued: bool,
+    },
+    UnitMorph(u16),
+    Unsiege {
+        queued: bool,
+    },
+    Siege {
+        queued: bool,
+    },
+    TrainFighter,
+    UnloadAll {
+        queued: bool,
+    },
+    Unload(u16),
+    MergeArchon,
+    HoldPosition {
+        queued: bool,
+    },
+    Burrow {
+        queued: bool,
+    },
+    Unburrow {
+        queued: bool,
+    },
+    CancelNuke,
+    Lift {
+        x: u16,
+        y: u16,
+    },
+    Research(u8),
+    CancelResearch,
+    Upgrade(u8),
+    CancelUpgrade,
+    CancelAddon,
+    BuildingMorph(u16),
+    Stim,
+    Sync([u8; 6]),
+    VoiceEnable,
+    VoiceDisable,
+    VoiceSquelch(u8),
+    VoiceUnsquelch(u8),
+    StartGame,
+    DownloadPercentage(u8),
+    ChangeGameSlot([u8; 5]),
+    NewNetPlayer([u8; 7]),
+    JoinedGame([u8; 17]),
+    ChangeRace {
+        slot: u8,
+        race: u8,
+    },
+    TeamGameTeam(u8),
+    UmsTeam(u8),
+    MeleeTeam {
+        slot: u8,
+        team: u8,
+    },
+    SwapPlayers {
+        slot_a: u8,
+        slot_b: u8,
+    },
+    SavedData([u8; 12]),
+    BriefingStart,
+    Latency(u8),
+    ReplaySpeed([u8; 9]),
+    LeaveGame {
+        reason: u8,
+    },
+    MinimapPing {
+        x: u16,
+        y: u16,
+    },
+    MergeDarkArchon,
+    MakeGamePublic,
+    Chat {
+        sender: u8,
+        message: &'a [u8],
+    },
+    // 1.21+ variants carry an extra u16 after every unit tag
+    RightClick121 {
+        x: u16,
+        y: u16,
+        target: u16,
+        unit_type: u16,
+        queued: bool,
+    },
+    TargetedOrder121 {
+        x: u16,
+        y: u16,
+        target: u16,
+        unit_type: u16,
+        order: u8,
+        queued: bool,
+    },
+    Unload121(u16),
+    Select121(UnitTags<'a>),
+    ShiftSelect121(UnitTags<'a>),
+    ShiftDeselect121(UnitTags<'a>),
+    /// An opcode we have no length for. Holds the rest of the frame block,
+    /// since the remaining commands in it cannot be located.
+    Unknown {
+        opcode: u8,
+        data: &'a [u8],
+    },
 }
 
-pub fn parse_frames_section(input: &[u8]) -> Result<(&[u8], Vec<Frame>), ParseError> {
-    let (input, _crc) = le_u32(input)?;
-    let (input, num_chunks) = le_u32(input)?;
+/// Unit tags of a selection. Pre-1.21 selections store each tag in 2 bytes,
+/// 1.21+ selections pad each tag to 4 bytes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UnitTags<'a> {
+    data: &'a [u8],
+    stride: usize,
+}
 
-    let mut chunks = Vec::new();
-    let mut input = input;
+impl<'a> UnitTags<'a> {
+    pub fn len(&self) -> usize {
+        self.data.len() / self.stride
+    }
 
-    for _ in 0..num_chunks {
-        let (new_input, chunk_size) = le_u32(input)?;
-        let (new_input, chunk_data) = take(chunk_size)(new_input)?;
-        chunks.push(chunk_data);
-        input = new_input;
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 
-    let mut combined_data = Vec::new();
-    for chunk in chunks {
-        match decompress_zlib_chunk(chunk) {
-            Ok(decompressed) => combined_data.extend_from_slice(&decompressed),
-            Err(_) => {
-                // assume uncompressed
-                combined_data.extend_from_slice(chunk);
-            }
+    pub fn iter(&self) -> impl Iterator<Item = u16> + 'a {
+        self.data
+            .chunks_exact(self.stride)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+    }
+}
+
+impl std::fmt::Debug for UnitTags<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UnitTags")
+            .field(&self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    Assign,
+    Select,
+    Add,
+    Unknown(u8),
+}
+
+impl From<u8> for HotkeyAction {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => HotkeyAction::Assign,
+            1 => HotkeyAction::Select,
+            2 => HotkeyAction::Add,
+            v => HotkeyAction::Unknown(v),
         }
     }
+}
+
+impl Command<'_> {
+    /// The opcode this command is stored under in the replay.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Command::KeepAlive => 0x05,
+            Command::SaveGame { .. } => 0x06,
+            Command::LoadGame { .. } => 0x07,
+            Command::RestartGame => 0x08,
+            Command::Select(_) => 0x09,
+            Command::ShiftSelect(_) => 0x0a,
+            Command::ShiftDeselect(_) => 0x0b,
+            Command::Build { .. } => 0x0c,
+            Command::Vision(_) => 0x0d,
+            Command::Alliance(_) => 0x0e,
+            Command::GameSpeed(_) => 0x0f,
+            Command::Pause => 0x10,
+            Command::Resume => 0x11,
+            Command::Cheat(_) => 0x12,
+            Command::Hotkey { .. } => 0x13,
+            Command::RightClick { .. } => 0x14,
+            Command::TargetedOrder { .. } => 0x15,
+            Command::CancelBuild => 0x18,
+            Command::CancelMorph => 0x19,
+            Command::Stop { .. } => 0x1a,
+            Command::CarrierStop => 0x1b,
+            Command::ReaverStop => 0x1c,
+            Command::OrderNothing => 0x1d,
+            Command::ReturnCargo { .. } => 0x1e,
+            Command::Train(_) => 0x1f,
+            Command::CancelTrain(_) => 0x20,
+            Command::Cloak { .. } => 0x21,
+            Command::Decloak { .. } => 0x22,
+            Command::UnitMorph(_) => 0x23,
+            Command::Unsiege { .. } => 0x25,
+            Command::Siege { .. } => 0x26,
+            Command::TrainFighter => 0x27,
+            Command::UnloadAll { .. } => 0x28,
+            Command::Unload(_) => 0x29,
+            Command::MergeArchon => 0x2a,
+            Command::HoldPosition { .. } => 0x2b,
+            Command::Burrow { .. } => 0x2c,
+            Command::Unburrow { .. } => 0x2d,
+            Command::CancelNuke => 0x2e,
+            Command::Lift { .. } => 0x2f,
+            Command::Research(_) => 0x30,
+            Command::CancelResearch => 0x31,
+            Command::Upgrade(_) => 0x32,
+            Command::CancelUpgrade => 0x33,
+            Command::CancelAddon => 0x34,
+            Command::BuildingMorph(_) => 0x35,
+            Command::Stim => 0x36,
+            Command::Sync(_) => 0x37,
+            Command::VoiceEnable => 0x38,
+            Command::VoiceDisable => 0x39,
+            Command::VoiceSquelch(_) => 0x3a,
+            Command::VoiceUnsquelch(_) => 0x3b,
+            Command::StartGame => 0x3c,
+            Command::DownloadPercentage(_) => 0x3d,
+            Command::ChangeGameSlot(_) => 0x3e,
+            Command::NewNetPlayer(_) => 0x3f,
+            Command::JoinedGame(_) => 0x40,
+            Command::ChangeRace { .. } => 0x41,
+            Command::TeamGameTeam(_) => 0x42,
+            Command::UmsTeam(_) => 0x43,
+            Command::MeleeTeam { .. } => 0x44,
+            Command::SwapPlayers { .. } => 0x45,
+            Command::SavedData(_) => 0x48,
+            Command::BriefingStart => 0x54,
+            Command::Latency(_) => 0x55,
+            Command::ReplaySpeed(_) => 0x56,
+            Command::LeaveGame { .. } => 0x57,
+            Command::MinimapPing { .. } => 0x58,
+            Command::MergeDarkArchon => 0x5a,
+            Command::MakeGamePublic => 0x5b,
+            Command::Chat { .. } => 0x5c,
+            Command::RightClick121 { .. } => 0x60,
+            Command::TargetedOrder121 { .. } => 0x61,
+            Command::Unload121(_) => 0x62,
+            Command::Select121(_) => 0x63,
+            Command::ShiftSelect121(_) => 0x64,
+            Command::ShiftDeselect121(_) => 0x65,
+            Command::Unknown { opcode, .. } => *opcode,
+        }
+    }
+}
 
-    let frames = parse_frames_data(&combined_data)?;
+/// Locates the commands section. It is only decompressed when iterated with
+/// `Frames`.
+pub fn parse_frames_section<'a>(
+    input: &'a [u8],
+    reader: SectionReader,
+    warnings: &mut Vec<ParseError>,
+) -> Result<(&'a [u8], LazySection), ParseError> {
+    let start = input;
+    let (input, size) = reader.read_size(input, Section::CommandsSize, warnings)?;
+    let offset = start.len() - input.len();
+    let (input, section) = reader
+        .read_lazy(input, size as usize, Section::Commands)
+        .map_err(|e| e.offset_by(offset))?;
+    Ok((input, section.offset_by(offset)))
+}
 
-    Ok((input, frames))
+/// Iterator over the frames of a commands section. Decompresses one chunk at
+/// a time and stops at the first frame that cannot be read; `error` then
+/// tells why. The checksum is verified once the last chunk has been read.
+pub struct Frames<'a> {
+    section: &'a LazySection,
+    chunks: Chunks<'a>,
+    hasher: crc32fast::Hasher,
+    buffer: Vec<u8>,
+    pos: usize,
+    /// Offset of `buffer` in the decompressed section
+    buffer_offset: usize,
+    last_frame: Option<u32>,
+    error: Option<ParseError>,
+    done: bool,
 }
 
-fn parse_frames_data(data: &[u8]) -> Result<Vec<Frame>, ParseError> {
-    let mut frames = Vec::new();
-    let mut input = data;
+impl<'a> Frames<'a> {
+    pub fn new(section: &'a LazySection) -> Self {
+        Self {
+            section,
+            chunks: section.chunks(),
+            hasher: crc32fast::Hasher::new(),
+            buffer: Vec::new(),
+            pos: 0,
+            buffer_offset: 0,
+            last_frame: None,
+            error: None,
+            done: false,
+        }
+    }
 
-    while !input.is_empty() {
-        match parse_frame(input) {
-            Ok((remaining, frame)) => {
-                frames.push(frame);
-                input = remaining;
+    /// Why iteration ended before the end of the section, or that the
+    /// section did not match its checksum
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Records why the frames ended early, at the frame being read
+    fn stop(&mut self, error: Option<ParseError>) {
+        self.done = true;
+
+        let available = &self.buffer[self.pos..];
+        let error = match error.or_else(|| self.section.error().cloned()) {
+            Some(error) => error,
+            None if available.is_empty() => {
+                let actual = std::mem::take(&mut self.hasher).finalize();
+                self.error = self.section.checksum_mismatch(actual);
+                return;
+            }
+            None => ParseError::Truncated(Location {
+                section: Some(Section::Commands),
+                ..Location::default()
+            }),
+        };
+
+        let frame = match available.get(..4) {
+            Some(header) => Some(u32::from_le_bytes([
+                header[0], header[1], header[2], header[3],
+            ])),
+            None => self.last_frame,
+        };
+        let error = error.at_data_offset(self.buffer_offset + self.pos);
+        self.error = Some(match frame {
+            Some(frame) => error.at_frame(frame),
+            None => error,
+        });
+    }
+
+    /// The next complete frame in the buffer, if there is one
+    fn take_frame(&mut self) -> Option<Frame> {
+        let available = &self.buffer[self.pos..];
+        let header = available.get(..FRAME_HEADER_SIZE)?;
+        let block_len = header[4];
+        let block = available.get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + block_len as usize)?;
+
+        let mut frame = Frame {
+            frame_number: u32::from_le_bytes([header[0], header[1], header[2], header[3]]),
+            block: [0; MAX_BLOCK_SIZE],
+            block_len,
+        };
+        frame.block[..block.len()].copy_from_slice(block);
+        self.pos += FRAME_HEADER_SIZE + block.len();
+        self.last_frame = Some(frame.frame_number);
+        Some(frame)
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        while !self.done {
+            if let Some(frame) = self.take_frame() {
+                return Some(frame);
+            }
+
+            // Frames can span chunks; keep the unread tail and append the
+            // next chunk to it
+            match self.chunks.next() {
+                Some(Ok(chunk)) => {
+                    self.hasher.update(&chunk);
+                    self.buffer.drain(..self.pos);
+                    self.buffer_offset += self.pos;
+                    self.pos = 0;
+                    self.buffer.extend_from_slice(&chunk);
+                }
+                Some(Err(e)) => self.stop(Some(e)),
+                None => self.stop(None),
             }
-            Err(_) => break,
         }
+        None
     }
+}
 
-    Ok(frames)
+/// Iterator over the commands of a frame. Stops at the first command that
+/// cannot be read; `error` then tells why.
+pub struct Commands<'a> {
+    input: &'a [u8],
+    frame_number: Option<u32>,
+    error: Option<ParseError>,
 }
 
-fn parse_frame(input: &[u8]) -> Result<(&[u8], Frame), ParseError> {
-    let (input, frame_number) = le_u32(input)?;
-    let (input, block_size) = le_u8(input)?;
-    let (input, frame_data) = take(block_size)(input)?;
+impl<'a> Commands<'a> {
+    /// Iterates over the commands of an undecoded frame block
+    pub fn new(block: &'a [u8]) -> Self {
+        Self {
+            input: block,
+            frame_number: None,
+            error: None,
+        }
+    }
+
+    /// The part of the block not read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
 
-    let commands = parse_commands(frame_data)?;
+    /// Why iteration ended before the end of the block
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
 
-    Ok((
-        input,
-        Frame {
-            frame_number,
-            commands,
-        },
-    ))
+    /// Like `next`, but also returns the bytes the command was decoded from
+    pub fn next_with_bytes(&mut self) -> Option<(PlayerCommand<'a>, &'a [u8])> {
+        let input = self.input;
+        let command = self.next()?;
+        Some((command, &input[..input.len() - self.input.len()]))
+    }
 }
 
-fn parse_commands(data: &[u8]) -> Result<Vec<Command>, ParseError> {
-    let mut commands = Vec::new();
-    let mut input = data;
+impl<'a> Iterator for Commands<'a> {
+    type Item = PlayerCommand<'a>;
 
-    while !input.is_empty() {
-        match parse_command(input) {
+    fn next(&mut self) -> Option<PlayerCommand<'a>> {
+        if self.input.is_empty() {
+            return None;
+        }
+        match parse_command(self.input) {
             Ok((remaining, command)) => {
-                commands.push(command);
-                input = remaining;
+                self.input = remaining;
+                Some(command)
+            }
+            Err(e) => {
+                let mut e = e.in_section(Section::Commands);
+                if let Some(&opcode) = self.input.get(1) {
+                    e = e.with_opcode(opcode);
+                }
+                if let Some(frame) = self.frame_number {
+                    e = e.at_frame(frame);
+                }
+                self.error = Some(e);
+                self.input = &[];
+                None
             }
-            Err(_) => break,
         }
     }
-
-    Ok(commands)
 }
 
-fn parse_command(input: &[u8]) -> Result<(&[u8], Command), ParseError> {
-    let (input, _player_id) = le_u8(input)?;
-    let (input, command_type) = le_u8(input)?;
+fn parse_command(input: &[u8]) -> Result<(&[u8], PlayerCommand<'_>), ParseError> {
+    let (input, player_id) = le_u8(input)?;
+    let (input, opcode) = le_u8(input)?;
 
-    let (input, data) = match command_type {
-        // Commands with no additional data
-        0x18 | 0x19 | 0x10 | 0x11 | 0x08 | 0x5a | 0x5b | 0x1b | 0x1c | 0x1d | 0x27 | 0x2a
-        | 0x2e | 0x31 | 0x33 | 0x34 | 0x36 | 0x38 | 0x39 | 0x3c | 0x54 => (input, Vec::new()),
-        // Commands with 1 byte
-        0x0f | 0x20 | 0x21 | 0x22 | 0x30 | 0x32 | 0x55 | 0x57 => {
-            let (input, byte) = le_u8(input)?;
-            (input, vec![byte])
+    let (input, command) = match opcode {
+        0x05 => (input, Command::KeepAlive),
+        0x06 | 0x07 => {
+            let (input, save_info) = le_u32(input)?;
+            let (input, filename) = take_till(|b| b == 0)(input)?;
+            let (input, _) = take(1usize)(input)?;
+            if opcode == 0x06 {
+                (
+                    input,
+                    Command::SaveGame {
+                        save_info,
+                        filename,
+                    },
+                )
+            } else {
+                (
+                    input,
+                    Command::LoadGame {
+                        save_info,
+                        filename,
+                    },
+                )
+            }
         }
-        // Commands with 2 bytes
-        0x29 | 0x62 => {
-            let (input, data) = take(2usize)(input)?;
-            (input, data.to_vec())
+        0x08 => (input, Command::RestartGame),
+        0x09 => {
+            let (input, tags) = parse_unit_tags(input, 2)?;
+            (input, Command::Select(tags))
         }
-        0x5c => {
-            let (input, sender) = le_u8(input)?;
-            let (input, message_data) = take(80usize)(input)?;
-            let mut data = vec![sender];
-            data.extend_from_slice(message_data);
-            (input, data)
-        }
-        _ => {
-            if input.is_empty() {
-                (input, Vec::new())
+        0x0a => {
+            let (input, tags) = parse_unit_tags(input, 2)?;
+            (input, Command::ShiftSelect(tags))
+        }
+        0x0b => {
+            let (input, tags) = parse_unit_tags(input, 2)?;
+            (input, Command::ShiftDeselect(tags))
+        }
+        0x0c => {
+            let (input, order) = le_u8(input)?;
+            let (input, x) = le_u16(input)?;
+            let (input, y) = le_u16(input)?;
+            let (input, unit_type) = le_u16(input)?;
+            (
+                input,
+                Command::Build {
+                    order,
+                    x,
+                    y,
+                    unit_type,
+                },
+            )
+        }
+        0x0d => {
+            let (input, mask) = le_u16(input)?;
+            (input, Command::Vision(mask))
+        }
+        0x0e => {
+            let (input, mask) = le_u32(input)?;
+            (input, Command::Alliance(mask))
+        }
+        0x0f => {
+            let (input, speed) = le_u8(input)?;
+            (input, Command::GameSpeed(speed))
+        }
+        0x10 => (input, Command::Pause),
+        0x11 => (input, Command::Resume),
+        0x12 => {
+            let (input, flags) = le_u32(input)?;
+            (input, Command::Cheat(flags))
+        }
+        0x13 => {
+            let (input, action) = le_u8(input)?;
+            let (input, group) = le_u8(input)?;
+            (
+                input,
+                Command::Hotkey {
+                    action: action.into(),
+                    group,
+                },
+            )
+        }
+        0x14 | 0x60 => {
+            let (input, x) = le_u16(input)?;
+            let (input, y) = le_u16(input)?;
+            let (input, target) = le_u16(input)?;
+            let input = if opcode == 0x60 {
+                take(2usize)(input)?.0
+            } else {
+                input
+            };
+            let (input, unit_type) = le_u16(input)?;
+            let (input, queued) = le_u8(input)?;
+            let queued = queued != 0;
+            if opcode == 0x60 {
+                (
+                    input,
+                    Command::RightClick121 {
+                        x,
+                        y,
+                        target,
+                        unit_type,
+                        queued,
+                    },
+                )
+            } else {
+                (
+                    input,
+                    Command::RightClick {
+                        x,
+                        y,
+                        target,
+                        unit_type,
+                        queued,
+                    },
+                )
+            }
+        }
+        0x15 | 0x61 => {
+            let (input, x) = le_u16(input)?;
+            let (input, y) = le_u16(input)?;
+            let (input, target) = le_u16(input)?;
+            let input = if opcode == 0x61 {
+                take(2usize)(input)?.0
+            } else {
+                input
+            };
+            let (input, unit_type) = le_u16(input)?;
+            let (input, order) = le_u8(input)?;
+            let (input, queued) = le_u8(input)?;
+            let queued = queued != 0;
+            if opcode == 0x61 {
+                (
+                    input,
+                    Command::TargetedOrder121 {
+                        x,
+                        y,
+                        target,
+                        unit_type,
+                        order,
+                        queued,
+                    },
+                )
             } else {
-                let (input, byte) = le_u8(input)?;
-                (input, vec![byte])
+                (
+                    input,
+                    Command::TargetedOrder {
+                        x,
+                        y,
+                        target,
+                        unit_type,
+                        order,
+                        queued,
+                    },
+                )
             }
         }
+        0x18 => (input, Command::CancelBuild),
+        0x19 => (input, Command::CancelMorph),
+        0x1a | 0x1e | 0x21 | 0x22 | 0x25 | 0x26 | 0x28 | 0x2b | 0x2c | 0x2d => {
+            let (input, queued) = le_u8(input)?;
+            let queued = queued != 0;
+            let command = match opcode {
+                0x1a => Command::Stop { queued },
+                0x1e => Command::ReturnCargo { queued },
+                0x21 => Command::Cloak { queued },
+                0x22 => Command::Decloak { queued },
+                0x25 => Command::Unsiege { queued },
+                0x26 => Command::Siege { queued },
+                0x28 => Command::UnloadAll { queued },
+                0x2b => Command::HoldPosition { queued },
+                0x2c => Command::Burrow { queued },
+                _ => Command::Unburrow { queued },
+            };
+            (input, command)
+        }
+        0x1b => (input, Command::CarrierStop),
+        0x1c => (input, Command::ReaverStop),
+        0x1d => (input, Command::OrderNothing),
+        0x1f => {
+            let (input, unit_type) = le_u16(input)?;
+            (input, Command::Train(unit_type))
+        }
+        0x20 => {
+            let (input, tag) = le_u16(input)?;
+            (input, Command::CancelTrain(tag))
+        }
+        0x23 => {
+            let (input, unit_type) = le_u16(input)?;
+            (input, Command::UnitMorph(unit_type))
+        }
+        0x27 => (input, Command::TrainFighter),
+        0x29 => {
+            let (input, tag) = le_u16(input)?;
+            (input, Command::Unload(tag))
+        }
+        0x2a => (input, Command::MergeArchon),
+        0x2e => (input, Command::CancelNuke),
+        0x2f => {
+            let (input, x) = le_u16(input)?;
+            let (input, y) = le_u16(input)?;
+            (input, Command::Lift { x, y })
+        }
+        0x30 => {
+            let (input, tech) = le_u8(input)?;
+            (input, Command::Research(tech))
+        }
+        0x31 => (input, Command::CancelResearch),
+        0x32 => {
+            let (input, upgrade) = le_u8(input)?;
+            (input, Command::Upgrade(upgrade))
+        }
+        0x33 => (input, Command::CancelUpgrade),
+        0x34 => (input, Command::CancelAddon),
+        0x35 => {
+            let (input, unit_type) = le_u16(input)?;
+            (input, Command::BuildingMorph(unit_type))
+        }
+        0x36 => (input, Command::Stim),
+        0x37 => {
+            let (input, data) = take_array(input)?;
+            (input, Command::Sync(data))
+        }
+        0x38 => (input, Command::VoiceEnable),
+        0x39 => (input, Command::VoiceDisable),
+        0x3a => {
+            let (input, slot) = le_u8(input)?;
+            (input, Command::VoiceSquelch(slot))
+        }
+        0x3b => {
+            let (input, slot) = le_u8(input)?;
+            (input, Command::VoiceUnsquelch(slot))
+        }
+        0x3c => (input, Command::StartGame),
+        0x3d => {
+            let (input, percent) = le_u8(input)?;
+            (input, Command::DownloadPercentage(percent))
+        }
+        0x3e => {
+            let (input, data) = take_array(input)?;
+            (input, Command::ChangeGameSlot(data))
+        }
+        0x3f => {
+            let (input, data) = take_array(input)?;
+            (input, Command::NewNetPlayer(data))
+        }
+        0x40 => {
+            let (input, data) = take_array(input)?;
+            (input, Command::JoinedGame(data))
+        }
+        0x41 => {
+            let (input, slot) = le_u8(input)?;
+            let (input, race) = le_u8(input)?;
+            (input, Command::ChangeRace { slot, race })
+        }
+        0x42 => {
+            let (input, team) = le_u8(input)?;
+            (input, Command::TeamGameTeam(team))
+        }
+        0x43 => {
+            let (input, team) = le_u8(input)?;
+            (input, Command::UmsTeam(team))
+        }
+        0x44 => {
+            let (input, slot) = le_u8(input)?;
+            let (input, team) = le_u8(input)?;
+            (input, Command::MeleeTeam { slot, team })
+        }
+        0x45 => {
+            let (input, slot_a) = le_u8(input)?;
+            let (input, slot_b) = le_u8(input)?;
+            (input, Command::SwapPlayers { slot_a, slot_b })
+        }
+        0x48 => {
+            let (input, data) = take_array(input)?;
+            (input, Command::SavedData(data))
+        }
+        0x54 => (input, Command::BriefingStart),
+        0x55 => {
+            let (input, latency) = le_u8(input)?;
+            (input, Command::Latency(latency))
+        }
+        0x56 => {
+            let (input, data) = take_array(input)?;
+            (input, Command::ReplaySpeed(data))
+        }
+        0x57 => {
+            let (input, reason) = le_u8(input)?;
+            (input, Command::LeaveGame { reason })
+        }
+        0x58 => {
+            let (input, x) = le_u16(input)?;
+            let (input, y) = le_u16(input)?;
+            (input, Command::MinimapPing { x, y })
+        }
+        0x5a => (input, Command::MergeDarkArchon),
+        0x5b => (input, Command::MakeGamePublic),
+        0x5c => {
+            let (input, sender) = le_u8(input)?;
+            let (input, message) = take(80usize)(input)?;
+            (input, Command::Chat { sender, message })
+        }
+        0x62 => {
+            let (input, tag) = le_u16(input)?;
+            let (input, _) = take(2usize)(input)?;
+            (input, Command::Unload121(tag))
+        }
+        0x63 => {
+            let (input, tags) = parse_unit_tags(input, 4)?;
+            (input, Command::Select121(tags))
+        }
+        0x64 => {
+            let (input, tags) = parse_unit_tags(input, 4)?;
+            (input, Command::ShiftSelect121(tags))
+        }
+        0x65 => {
+            let (input, tags) = parse_unit_tags(input, 4)?;
+            (input, Command::ShiftDeselect121(tags))
+        }
+        _ => (
+            &input[input.len()..],
+            Command::Unknown {
+                opcode,
+                data: input,
+            },
+        ),
     };
 
-    Ok((input, Command { command_type, data }))
+    Ok((input, PlayerCommand { player_id, command }))
+}
+
+/// Parses a unit count followed by that many unit tags of `stride` bytes
+fn parse_unit_tags(input: &[u8], stride: usize) -> Result<(&[u8], UnitTags<'_>), ParseError> {
+    let (input, count) = le_u8(input)?;
+    let (input, data) = take(count as usize * stride)(input)?;
+    Ok((input, UnitTags { data, stride }))
+}
+
+fn take_array<const N: usize>(input: &[u8]) -> Result<(&[u8], [u8; N]), ParseError> {
+    let (input, data) = take(N)(input)?;
+    let mut array = [0u8; N];
+    array.copy_from_slice(data);
+    Ok((input, array))
 }
 
-fn decompress_zlib_chunk(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    use flate2::read::ZlibDecoder;
-    use std::io::Read;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Payload length of every fixed-size command
+    const LENGTHS: &[(u8, usize)] = &[
+        (0x05, 0),
+        (0x08, 0),
+        (0x0c, 7),
+        (0x0d, 2),
+        (0x0e, 4),
+        (0x0f, 1),
+        (0x10, 0),
+        (0x11, 0),
+        (0x12, 4),
+        (0x13, 2),
+        (0x14, 9),
+        (0x15, 10),
+        (0x18, 0),
+        (0x19, 0),
+        (0x1a, 1),
+        (0x1b, 0),
+        (0x1c, 0),
+        (0x1d, 0),
+        (0x1e, 1),
+        (0x1f, 2),
+        (0x20, 2),
+        (0x21, 1),
+        (0x22, 1),
+        (0x23, 2),
+        (0x25, 1),
+        (0x26, 1),
+        (0x27, 0),
+        (0x28, 1),
+        (0x29, 2),
+        (0x2a, 0),
+        (0x2b, 1),
+        (0x2c, 1),
+        (0x2d, 1),
+        (0x2e, 0),
+        (0x2f, 4),
+        (0x30, 1),
+        (0x31, 0),
+        (0x32, 1),
+        (0x33, 0),
+        (0x34, 0),
+        (0x35, 2),
+        (0x36, 0),
+        (0x37, 6),
+        (0x38, 0),
+        (0x39, 0),
+        (0x3a, 1),
+        (0x3b, 1),
+        (0x3c, 0),
+        (0x3d, 1),
+        (0x3e, 5),
+        (0x3f, 7),
+        (0x40, 17),
+        (0x41, 2),
+        (0x42, 1),
+        (0x43, 1),
+        (0x44, 2),
+        (0x45, 2),
+        (0x48, 12),
+        (0x54, 0),
+        (0x55, 1),
+        (0x56, 9),
+        (0x57, 1),
+        (0x58, 4),
+        (0x5a, 0),
+        (0x5b, 0),
+        (0x5c, 81),
+        (0x60, 11),
+        (0x61, 12),
+        (0x62, 4),
+    ];
 
-    let mut decoder = ZlibDecoder::new(data);
-    let mut decompressed = Vec::new();
-    decoder.read_to_end(&mut decompressed)?;
-    Ok(decompressed)
+    /// Parses one command followed by a marker byte, returning the command
+    /// and what it left
+    fn parse(opcode: u8, payload: &[u8]) -> Result<(Vec<u8>, u8), ParseError> {
+        let mut input = vec![3, opcode];
+        input.extend(payload);
+        input.push(0xaa);
+        let (rest, command) = parse_command(&input)?;
+        assert_eq!(command.player_id, 3);
+        Ok((rest.to_vec(), command.command.opcode()))
+    }
+
+    #[test]
+    fn fixed_size_commands_have_their_length() {
+        for &(opcode, len) in LENGTHS {
+            let payload = vec![1; len];
+            let (rest, parsed) = parse(opcode, &payload).unwrap();
+            assert_eq!(rest, [0xaa], "opcode {opcode:#04x}");
+            assert_eq!(parsed, opcode);
+
+            if len > 0 {
+                let input = [&[3, opcode][..], &payload[..len - 1]].concat();
+                assert!(parse_command(&input).is_err(), "opcode {opcode:#04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn selections_are_sized_by_their_count() {
+        let opcodes = [
+            (0x09, 2),
+            (0x0a, 2),
+            (0x0b, 2),
+            (0x63, 4),
+            (0x64, 4),
+            (0x65, 4),
+        ];
+        for (opcode, tag_size) in opcodes {
+            let mut payload = vec![3];
+            payload.extend(vec![1; 3 * tag_size]);
+            let (rest, _) = parse(opcode, &payload).unwrap();
+            assert_eq!(rest, [0xaa], "opcode {opcode:#04x}");
+        }
+    }
+
+    #[test]
+    fn save_and_load_end_at_the_filename_terminator() {
+        for opcode in [0x06, 0x07] {
+            let payload = [&[0, 0, 0, 0][..], b"game.rep\0"].concat();
+            let (rest, _) = parse(opcode, &payload).unwrap();
+            assert_eq!(rest, [0xaa]);
+        }
+    }
+
+    #[test]
+    fn unknown_opcodes_take_the_rest_of_the_block() {
+        let (_, command) = parse_command(&[3, 0xf0, 1, 2]).unwrap();
+        assert_eq!(
+            command.command,
+            Command::Unknown {
+                opcode: 0xf0,
+                data: &[1, 2]
+            }
+        );
+        assert!(parse(0xf0, &[]).unwrap().0.is_empty());
+    }
 }
@@ -1,26 +1,267 @@
-use crate::replay_parser::ParseError;
+use crate::replay_parser::{
+    section::{Section, SectionReader},
+    text, ParseError,
+};
 
 use nom::{
     bytes::complete::take,
     number::complete::{le_u16, le_u32, le_u8},
     IResult,
 };
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct GameInfo {
+    pub engine: Engine,
     pub frames: u32,
     pub start_time: SystemTime,
+    pub title: String,
+    pub map_width: u16,
+    pub map_height: u16,
+    pub available_slots: u8,
+    pub game_speed: GameSpeed,
+    pub game_type: GameType,
+    pub game_sub_type: u16,
+    pub host_name: String,
+    pub map_name: String,
     pub player_structs: Vec<PlayerStruct>,
+    /// The decompressed section as stored in the replay. `to_bytes` writes
+    /// the fields above over it, so undecoded bytes survive a round trip.
+    pub raw: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    StarCraft,
+    BroodWar,
+    Unknown(u8),
+}
+
+impl From<u8> for Engine {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Engine::StarCraft,
+            1 => Engine::BroodWar,
+            v => Engine::Unknown(v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameSpeed {
+    Slowest,
+    Slower,
+    Slow,
+    Normal,
+    Fast,
+    Faster,
+    Fastest,
+    Unknown(u8),
+}
+
+impl From<u8> for GameSpeed {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => GameSpeed::Slowest,
+            1 => GameSpeed::Slower,
+            2 => GameSpeed::Slow,
+            3 => GameSpeed::Normal,
+            4 => GameSpeed::Fast,
+            5 => GameSpeed::Faster,
+            6 => GameSpeed::Fastest,
+            v => GameSpeed::Unknown(v),
+        }
+    }
+}
+
+impl fmt::Display for GameSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameSpeed::Slowest => write!(f, "Slowest"),
+            GameSpeed::Slower => write!(f, "Slower"),
+            GameSpeed::Slow => write!(f, "Slow"),
+            GameSpeed::Normal => write!(f, "Normal"),
+            GameSpeed::Fast => write!(f, "Fast"),
+            GameSpeed::Faster => write!(f, "Faster"),
+            GameSpeed::Fastest => write!(f, "Fastest"),
+            GameSpeed::Unknown(v) => write!(f, "Unknown ({v})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameType {
+    None,
+    Custom,
+    Melee,
+    FreeForAll,
+    OneOnOne,
+    CaptureTheFlag,
+    Greed,
+    Slaughter,
+    SuddenDeath,
+    Ladder,
+    UseMapSettings,
+    TeamMelee,
+    TeamFreeForAll,
+    TeamCaptureTheFlag,
+    TopVsBottom,
+    IronManLadder,
+    Unknown(u16),
+}
+
+impl From<u16> for GameType {
+    fn from(value: u16) -> Self {
+        match value {
+            0x00 => GameType::None,
+            0x01 => GameType::Custom,
+            0x02 => GameType::Melee,
+            0x03 => GameType::FreeForAll,
+            0x04 => GameType::OneOnOne,
+            0x05 => GameType::CaptureTheFlag,
+            0x06 => GameType::Greed,
+            0x07 => GameType::Slaughter,
+            0x08 => GameType::SuddenDeath,
+            0x09 => GameType::Ladder,
+            0x0a => GameType::UseMapSettings,
+            0x0b => GameType::TeamMelee,
+            0x0c => GameType::TeamFreeForAll,
+            0x0d => GameType::TeamCaptureTheFlag,
+            0x0f => GameType::TopVsBottom,
+            0x10 => GameType::IronManLadder,
+            v => GameType::Unknown(v),
+        }
+    }
+}
+
+impl From<GameType> for u16 {
+    fn from(value: GameType) -> Self {
+        match value {
+            GameType::None => 0x00,
+            GameType::Custom => 0x01,
+            GameType::Melee => 0x02,
+            GameType::FreeForAll => 0x03,
+            GameType::OneOnOne => 0x04,
+            GameType::CaptureTheFlag => 0x05,
+            GameType::Greed => 0x06,
+            GameType::Slaughter => 0x07,
+            GameType::SuddenDeath => 0x08,
+            GameType::Ladder => 0x09,
+            GameType::UseMapSettings => 0x0a,
+            GameType::TeamMelee => 0x0b,
+            GameType::TeamFreeForAll => 0x0c,
+            GameType::TeamCaptureTheFlag => 0x0d,
+            GameType::TopVsBottom => 0x0f,
+            GameType::IronManLadder => 0x10,
+            GameType::Unknown(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for GameType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameType::None => write!(f, "None"),
+            GameType::Custom => write!(f, "Custom"),
+            GameType::Melee => write!(f, "Melee"),
+            GameType::FreeForAll => write!(f, "Free For All"),
+            GameType::OneOnOne => write!(f, "One on One"),
+            GameType::CaptureTheFlag => write!(f, "Capture The Flag"),
+            GameType::Greed => write!(f, "Greed"),
+            GameType::Slaughter => write!(f, "Slaughter"),
+            GameType::SuddenDeath => write!(f, "Sudden Death"),
+            GameType::Ladder => write!(f, "Ladder"),
+            GameType::UseMapSettings => write!(f, "Use Map Settings"),
+            GameType::TeamMelee => write!(f, "Team Melee"),
+            GameType::TeamFreeForAll => write!(f, "Team Free For All"),
+            GameType::TeamCaptureTheFlag => write!(f, "Team Capture The Flag"),
+            GameType::TopVsBottom => write!(f, "Top vs Bottom"),
+            GameType::IronManLadder => write!(f, "Iron Man Ladder"),
+            GameType::Unknown(v) => write!(f, "Unknown ({v})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PlayerStruct {
     pub slot_id: u16,
+    /// Player id used by the command stream
+    pub id: u8,
+    pub player_type: PlayerType,
+    pub race: Race,
+    pub team: u8,
     pub name: String,
+    /// Color index, only present for the 8 playable slots
+    pub color: Option<u32>,
+    pub is_observer: bool,
 }
 
-#[derive(Debug)]
+impl PlayerStruct {
+    /// Whether this slot holds an actual participant of the game
+    pub fn is_active(&self) -> bool {
+        matches!(self.player_type, PlayerType::Human | PlayerType::Computer)
+            && !self.name.is_empty()
+    }
+
+    /// Writes the struct over its stored form. Player types and races that
+    /// several values decode to are only written when they changed.
+    fn write(&self, data: &mut [u8]) {
+        data[0..2].copy_from_slice(&self.slot_id.to_le_bytes());
+        data[4] = self.id;
+        if PlayerType::from(data[8]) != self.player_type {
+            data[8] = match self.player_type {
+                PlayerType::Inactive => 0,
+                PlayerType::Computer => 1,
+                PlayerType::Human => 2,
+                PlayerType::RescuePassive => 3,
+                PlayerType::Open => 6,
+                PlayerType::Neutral => 7,
+                PlayerType::Closed => 8,
+                PlayerType::Unknown(v) => v,
+            };
+        }
+        if Race::from(data[9]) != self.race {
+            data[9] = match self.race {
+                Race::Zerg => 0,
+                Race::Terran => 1,
+                Race::Protoss => 2,
+                Race::Unknown => 6,
+            };
+        }
+        data[10] = self.team;
+        write_string(&mut data[11..36], &self.name);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerType {
+    Inactive,
+    Computer,
+    Human,
+    RescuePassive,
+    Open,
+    Neutral,
+    Closed,
+    Unknown(u8),
+}
+
+impl From<u8> for PlayerType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => PlayerType::Inactive,
+            1 | 5 => PlayerType::Computer,
+            2 => PlayerType::Human,
+            3 => PlayerType::RescuePassive,
+            6 => PlayerType::Open,
+            7 => PlayerType::Neutral,
+            8 => PlayerType::Closed,
+            v => PlayerType::Unknown(v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Race {
     Zerg,
     Terran,
@@ -28,6 +269,17 @@ pub enum Race {
     Unknown,
 }
 
+impl Race {
+    pub fn letter(&self) -> char {
+        match self {
+            Race::Zerg => 'Z',
+            Race::Terran => 'T',
+            Race::Protoss => 'P',
+            Race::Unknown => '?',
+        }
+    }
+}
+
 impl From<u8> for Race {
     fn from(value: u8) -> Self {
         match value {
@@ -39,56 +291,105 @@ impl From<u8> for Race {
     }
 }
 
-pub fn parse_game_info_section(input: &[u8]) -> Result<(&[u8], GameInfo), ParseError> {
-    let (input, _crc) = le_u32(input)?;
-    let (input, num_chunks) = le_u32(input)?;
+/// Decompressed size of the game info section
+pub const GAME_INFO_SIZE: usize = 0x279;
+/// Offset of the first of the 12 player structs
+const PLAYER_STRUCTS_OFFSET: usize = 0xa1;
+const PLAYER_STRUCT_SIZE: usize = 36;
+/// Offset of the colors of the 8 playable slots
+const PLAYER_COLORS_OFFSET: usize = 0x251;
 
-    if num_chunks != 1 {
-        return Err(ParseError::InvalidData(format!(
-            "Expected 1 chunk for game info, got {num_chunks}"
-        )));
-    }
+pub fn parse_game_info_section<'a>(
+    input: &'a [u8],
+    reader: SectionReader,
+    warnings: &mut Vec<ParseError>,
+) -> Result<(&'a [u8], GameInfo), ParseError> {
+    let (input, data) = reader.read(input, GAME_INFO_SIZE, Section::GameInfo, warnings)?;
 
-    let (input, chunk_size) = le_u32(input)?;
-    let (input, chunk_data) = take(chunk_size)(input)?;
+    let (_, mut game_info) =
+        parse_game_info_data(&data).map_err(|e| e.in_section(Section::GameInfo))?;
+    game_info.raw = data;
 
-    let decompressed_data = match decompress_chunk(chunk_data) {
-        Ok(data) => data,
-        Err(_) => chunk_data.to_vec(),
-    };
+    Ok((input, game_info))
+}
 
-    let (_, game_info) = parse_game_info_data(&decompressed_data)?;
+impl GameInfo {
+    /// Encodes the section as it is stored in the replay
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.raw.clone();
+        data.resize(GAME_INFO_SIZE, 0);
 
-    Ok((input, game_info))
+        data[0] = match self.engine {
+            Engine::StarCraft => 0,
+            Engine::BroodWar => 1,
+            Engine::Unknown(v) => v,
+        };
+        data[1..5].copy_from_slice(&self.frames.to_le_bytes());
+        let start_time = self
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        data[8..12].copy_from_slice(&start_time.to_le_bytes());
+        write_string(&mut data[0x18..0x34], &self.title);
+        data[0x34..0x36].copy_from_slice(&self.map_width.to_le_bytes());
+        data[0x36..0x38].copy_from_slice(&self.map_height.to_le_bytes());
+        data[0x39] = self.available_slots;
+        data[0x3a] = match self.game_speed {
+            GameSpeed::Slowest => 0,
+            GameSpeed::Slower => 1,
+            GameSpeed::Slow => 2,
+            GameSpeed::Normal => 3,
+            GameSpeed::Fast => 4,
+            GameSpeed::Faster => 5,
+            GameSpeed::Fastest => 6,
+            GameSpeed::Unknown(v) => v,
+        };
+        data[0x3c..0x3e].copy_from_slice(&u16::from(self.game_type).to_le_bytes());
+        data[0x3e..0x40].copy_from_slice(&self.game_sub_type.to_le_bytes());
+        write_string(&mut data[0x48..0x60], &self.host_name);
+        write_string(&mut data[0x61..0x7b], &self.map_name);
+
+        for (index, player) in self.player_structs.iter().take(12).enumerate() {
+            let offset = PLAYER_STRUCTS_OFFSET + index * PLAYER_STRUCT_SIZE;
+            player.write(&mut data[offset..offset + PLAYER_STRUCT_SIZE]);
+            if let Some(color) = player.color.filter(|_| index < 8) {
+                let offset = PLAYER_COLORS_OFFSET + index * 4;
+                data[offset..offset + 4].copy_from_slice(&color.to_le_bytes());
+            }
+        }
+
+        data
+    }
 }
 
 fn parse_game_info_data(input: &[u8]) -> Result<(&[u8], GameInfo), ParseError> {
-    let (input, _engine) = nom::number::complete::i8(input)?;
+    let (input, engine) = le_u8(input)?;
     let (input, frames) = le_u32(input)?;
     let (input, _) = take(3usize)(input)?;
     let (input, start_time_unix) = le_u32(input)?;
     let (input, _) = take(12usize)(input)?;
 
     let (input, title_bytes) = take(28usize)(input)?;
-    let _title = parse_null_terminated_string(title_bytes);
+    let title = text::decode(title_bytes);
 
-    let (input, _map_width) = le_u16(input)?;
-    let (input, _map_height) = le_u16(input)?;
+    let (input, map_width) = le_u16(input)?;
+    let (input, map_height) = le_u16(input)?;
     let (input, _) = take(1usize)(input)?;
-    let (input, _available_slots_count) = le_u8(input)?;
-    let (input, _speed) = le_u8(input)?;
+    let (input, available_slots) = le_u8(input)?;
+    let (input, game_speed) = le_u8(input)?;
     let (input, _) = take(1usize)(input)?;
-    let (input, _game_type) = le_u16(input)?;
-    let (input, _sub_type) = le_u16(input)?;
+    let (input, game_type) = le_u16(input)?;
+    let (input, game_sub_type) = le_u16(input)?;
     let (input, _) = take(8usize)(input)?;
 
     let (input, host_bytes) = take(24usize)(input)?;
-    let _host = parse_null_terminated_string(host_bytes);
+    let host_name = text::decode(host_bytes);
 
     let (input, _) = take(1usize)(input)?;
 
     let (input, map_bytes) = take(26usize)(input)?;
-    let _map = parse_null_terminated_string(map_bytes);
+    let map_name = text::decode(map_bytes);
 
     let (input, _) = take(38usize)(input)?;
 
@@ -100,19 +401,37 @@ fn parse_game_info_data(input: &[u8]) -> Result<(&[u8], GameInfo), ParseError> {
         input = new_input;
     }
 
-    for _ in 0..8 {
-        let (new_input, _player_color) = parse_player_color(input)?;
+    for player in player_structs.iter_mut().take(8) {
+        let (new_input, color) = parse_player_color(input)?;
+        player.color = Some(color);
         input = new_input;
     }
 
+    // Only 8 slots can play; SC:R seats observers in the remaining ones
+    for (index, player) in player_structs.iter_mut().enumerate() {
+        player.is_observer =
+            player.player_type == PlayerType::Human && (index >= 8 || player.id >= 128);
+    }
+
     let start_time = UNIX_EPOCH + std::time::Duration::from_secs(start_time_unix as u64);
 
     Ok((
         input,
         GameInfo {
+            engine: engine.into(),
             frames,
             start_time,
+            title,
+            map_width,
+            map_height,
+            available_slots,
+            game_speed: game_speed.into(),
+            game_type: game_type.into(),
+            game_sub_type,
+            host_name,
+            map_name,
             player_structs,
+            raw: Vec::new(),
         },
     ))
 }
@@ -120,17 +439,29 @@ fn parse_game_info_data(input: &[u8]) -> Result<(&[u8], GameInfo), ParseError> {
 fn parse_player_struct(input: &[u8]) -> IResult<&[u8], PlayerStruct> {
     let (input, slot_id) = le_u16(input)?;
     let (input, _) = take(2usize)(input)?;
-    let (input, _id) = le_u8(input)?;
+    let (input, id) = le_u8(input)?;
     let (input, _) = take(3usize)(input)?;
-    let (input, _player_type) = le_u8(input)?;
+    let (input, player_type) = le_u8(input)?;
     let (input, race_value) = le_u8(input)?;
-    let (input, _team) = le_u8(input)?;
+    let (input, team) = le_u8(input)?;
     let (input, name_bytes) = take(25usize)(input)?;
 
-    let _race = Race::from(race_value);
-    let name = parse_null_terminated_string(name_bytes);
+    let race = Race::from(race_value);
+    let name = text::decode(name_bytes);
 
-    Ok((input, PlayerStruct { slot_id, name }))
+    Ok((
+        input,
+        PlayerStruct {
+            slot_id,
+            id,
+            player_type: player_type.into(),
+            race,
+            team,
+            name,
+            color: None,
+            is_observer: false,
+        },
+    ))
 }
 
 fn parse_player_color(input: &[u8]) -> IResult<&[u8], u32> {
@@ -138,17 +469,18 @@ fn parse_player_color(input: &[u8]) -> IResult<&[u8], u32> {
     Ok((input, color))
 }
 
-fn parse_null_terminated_string(bytes: &[u8]) -> String {
-    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
-    String::from_utf8_lossy(&bytes[..end]).to_string()
-}
-
-fn decompress_chunk(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    use flate2::read::ZlibDecoder;
-    use std::io::Read;
+/// Writes `value` into a fixed-size, null-terminated field. The field is left
+/// alone if it already decodes to `value`, so text that does not survive
+/// decoding is kept byte for byte.
+fn write_string(field: &mut [u8], value: &str) {
+    if text::decode(field) == value {
+        return;
+    }
 
-    let mut decoder = ZlibDecoder::new(data);
-    let mut decompressed = Vec::new();
-    decoder.read_to_end(&mut decompressed)?;
-    Ok(decompressed)
+    let mut len = value.len().min(field.len() - 1);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    field.fill(0);
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
 }
@@ -4,28 +4,54 @@ use nom::{bytes::complete::take, number::complete::le_u32};
 
 #[derive(Debug)]
 pub struct Header {
+    /// CRC32 of the version bytes, checked by the caller
+    pub checksum: u32,
     pub replay_version: String,
+    pub format: ReplayFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayFormat {
+    /// "reRS", written before 1.18 and compressed with PKWARE DCL
+    Legacy,
+    /// "seRS", written by 1.18+ and compressed with zlib
+    Modern,
 }
 
 pub fn parse_header(input: &[u8]) -> Result<(&[u8], Header), ParseError> {
-    let (input, _crc) = le_u32(input)?;
+    let (input, crc) = le_u32(input)?;
     let (input, chunks) = le_u32(input)?;
     let (input, bytes) = le_u32(input)?;
     let (input, version_bytes) = take(4usize)(input)?;
-    let (input, _remaining_file_size) = le_u32(input)?;
 
     if chunks != 1 {
-        return Err(ParseError::InvalidData(format!(
+        return Err(ParseError::invalid(format!(
             "Expected chunks to be 1, got {chunks}"
         )));
     }
     if bytes != 4 {
-        return Err(ParseError::InvalidData(format!(
+        return Err(ParseError::invalid(format!(
             "Expected bytes to be 4, got {bytes}"
         )));
     }
 
     let replay_version = String::from_utf8_lossy(version_bytes).to_string();
 
-    Ok((input, Header { replay_version }))
+    let (input, format) = match version_bytes {
+        b"seRS" => {
+            let (input, _remaining_file_size) = le_u32(input)?;
+            (input, ReplayFormat::Modern)
+        }
+        b"reRS" => (input, ReplayFormat::Legacy),
+        _ => return Err(ParseError::UnsupportedVersion(replay_version)),
+    };
+
+    Ok((
+        input,
+        Header {
+            checksum: crc,
+            replay_version,
+            format,
+        },
+    ))
 }
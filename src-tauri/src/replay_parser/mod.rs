@@ -1,107 +1,343 @@
-use nom::{bytes::complete::take, number::complete::le_u32, IResult};
-
+pub mod anonymize;
+pub mod apm;
+pub mod build_order;
+pub mod chk;
 pub mod error;
+pub mod events;
+pub mod extended;
 pub mod frames;
 pub mod game_info;
 pub mod header;
+pub mod names;
+pub mod pkware;
+pub mod result;
+pub mod screp;
+pub mod section;
+pub mod text;
+pub mod time;
+pub mod writer;
 
+pub use anonymize::{anonymize, AnonymizeOptions};
+pub use apm::{MinuteApm, PlayerApm};
+pub use build_order::{BuildOrderItem, BuildOrderKind, PlayerBuildOrder};
+pub use chk::{Chk, StartLocation, Tileset};
 pub use error::ParseError;
-pub use frames::{Command, Frame};
-pub use game_info::{GameInfo, PlayerStruct};
+pub use events::{GameEvent, GameEventKind, Resume};
+pub use extended::{
+    BugFixes, CustomColors, ExtendedSections, GameConfig, Limits, RawSection, ShieldBattery, Skins,
+};
+pub use frames::{Command, Commands, Frame, Frames, HotkeyAction, PlayerCommand, UnitTags};
+pub use game_info::{Engine, GameInfo, GameSpeed, GameType, PlayerStruct, PlayerType, Race};
+pub use header::ReplayFormat;
+pub use result::{LeaveReason, Outcome, PlayerResult};
+pub use screp::ScrepReplay;
+pub use section::{LazySection, ParseOptions, Section};
+pub use time::FrameClock;
+pub use writer::{write_replay, write_replay_with_commands};
+
+use std::cell::OnceCell;
 
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
     pub sender_name: String,
     pub message: String,
     pub frame_number: u32,
+    pub timestamp_ms: u32,
     pub sender_id: u8,
 }
 
 #[derive(Debug)]
 pub struct ReplayParser<'a> {
     input: &'a [u8],
+    options: ParseOptions,
 }
 
 impl<'a> ReplayParser<'a> {
     /// Create a new parser from raw replay file data
     pub fn new(input: &'a [u8]) -> Self {
-        Self { input }
+        Self {
+            input,
+            options: ParseOptions::default(),
+        }
+    }
+
+    /// Checksum verification, strictness and leniency; all are off by
+    /// default
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
     }
 
+    /// Parses the replay. In lenient mode, only a damaged header or game
+    /// info section is an error; anything after is kept up to the failure,
+    /// which is reported in `ParsedReplay::warnings`. The commands are not
+    /// read until asked for.
     pub fn parse(&self) -> Result<ParsedReplay, ParseError> {
-        let (remaining, header) = header::parse_header(self.input)?;
+        let (remaining, header) = header::parse_header(self.input)
+            .map_err(|e| e.in_section(Section::ReplayId).offset_by(0))?;
+        let reader = section::SectionReader {
+            format: header.format,
+            options: self.options,
+        };
+        let mut warnings = Vec::new();
+        reader.verify_checksum(
+            Section::ReplayId,
+            header.checksum,
+            header.replay_version.as_bytes(),
+            &mut warnings,
+        )?;
+
+        let offset = self.offset(remaining);
+        let (remaining, game_info) =
+            game_info::parse_game_info_section(remaining, reader, &mut warnings)
+                .map_err(|e| e.offset_by(offset))?;
+
+        let mut replay = ParsedReplay {
+            game_info,
+            commands: LazySection::empty(reader, Section::Commands),
+            map: Chk::default(),
+            extended: ExtendedSections::default(),
+            warnings,
+            command_warnings: OnceCell::new(),
+        };
+        self.parse_sections(remaining, reader, &mut replay)?;
+
+        Ok(replay)
+    }
 
-        if header.replay_version != "seRS" {
-            return Err(ParseError::UnsupportedVersion(header.replay_version));
+    /// Reads the sections after the game info into `replay`, stopping at
+    /// the first one that fails in lenient mode
+    fn parse_sections(
+        &self,
+        remaining: &[u8],
+        reader: section::SectionReader,
+        replay: &mut ParsedReplay,
+    ) -> Result<(), ParseError> {
+        let offset = self.offset(remaining);
+        let result = frames::parse_frames_section(remaining, reader, &mut replay.warnings);
+        let Some((remaining, commands)) = self.recover(result, offset, replay)? else {
+            return Ok(());
+        };
+        replay.commands = commands.offset_by(offset);
+        if replay.commands.error().is_some() {
+            // reported once the frames are read
+            return Ok(());
+        }
+        // Lenient mode finds damage once the frames are read instead
+        if !self.options.lenient {
+            replay
+                .commands
+                .check(&mut replay.warnings)
+                .map_err(|e| e.offset_by(offset))?;
         }
 
-        let (remaining, game_info) = game_info::parse_game_info_section(remaining)?;
+        let offset = self.offset(remaining);
+        let result = chk::parse_map_section(remaining, reader, &mut replay.warnings);
+        let Some((remaining, map)) = self.recover(result, offset, replay)? else {
+            return Ok(());
+        };
+        replay.map = map;
 
-        let (remaining, _) = skip_section(remaining).map_err(|e| {
-            ParseError::InvalidData(format!("Failed to skip section size section: {e:?}"))
-        })?;
+        let offset = self.offset(remaining);
+        let mut warnings = Vec::new();
+        let result = extended::parse_extended_sections(remaining, reader, &mut warnings);
+        replay
+            .warnings
+            .extend(warnings.into_iter().map(|e| e.offset_by(offset)));
+        if let Some((_, extended)) = self.recover(result, offset, replay)? {
+            replay.extended = extended;
+        }
+        Ok(())
+    }
 
-        let (_, frames) = frames::parse_frames_section(remaining)?;
+    /// Turns an error into a warning in lenient mode
+    fn recover<T>(
+        &self,
+        result: Result<T, ParseError>,
+        offset: usize,
+        replay: &mut ParsedReplay,
+    ) -> Result<Option<T>, ParseError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.options.lenient => {
+                replay.warnings.push(e.offset_by(offset));
+                Ok(None)
+            }
+            Err(e) => Err(e.offset_by(offset)),
+        }
+    }
 
-        Ok(ParsedReplay { game_info, frames })
+    /// Absolute offset of `remaining` in the replay
+    fn offset(&self, remaining: &[u8]) -> usize {
+        self.input.len() - remaining.len()
     }
 }
 
 #[derive(Debug)]
 pub struct ParsedReplay {
     pub game_info: GameInfo,
-    pub frames: Vec<Frame>,
+    /// Still compressed; read it through `frames`
+    pub commands: LazySection,
+    pub map: Chk,
+    pub extended: ExtendedSections,
+    /// Problems skipped over in lenient mode while locating the sections
+    warnings: Vec<ParseError>,
+    /// Damage found by the first complete read of the commands
+    command_warnings: OnceCell<Vec<ParseError>>,
 }
 
 impl ParsedReplay {
+    /// Problems skipped over. Damage to the commands is only found when they
+    /// are read, so it is listed once something has read them all, such as
+    /// `apm` or `chat_messages`.
+    pub fn warnings(&self) -> impl Iterator<Item = &ParseError> {
+        self.warnings
+            .iter()
+            .chain(self.command_warnings.get().into_iter().flatten())
+    }
+
+    pub fn clock(&self) -> FrameClock {
+        FrameClock::new(self.game_info.game_speed)
+    }
+
     pub fn duration_ms(&self) -> u32 {
-        self.game_info.frames * 42 // 42 ms per frame
+        self.clock().ms(self.game_info.frames)
+    }
+
+    /// Resolves a command's player id to the player that issued it
+    pub fn player(&self, player_id: u8) -> Option<&PlayerStruct> {
+        self.game_info
+            .player_structs
+            .iter()
+            .find(|p| p.id == player_id && p.is_active())
+    }
+
+    /// Active players that are not observing
+    pub fn players(&self) -> impl Iterator<Item = &PlayerStruct> {
+        self.game_info
+            .player_structs
+            .iter()
+            .filter(|p| p.is_active() && !p.is_observer)
+    }
+
+    /// Players grouped by team, in team order. Melee games put everyone on
+    /// the same team; that is treated as a free-for-all.
+    fn teams(&self) -> Vec<Vec<&PlayerStruct>> {
+        let mut teams: Vec<(u8, Vec<&PlayerStruct>)> = Vec::new();
+        for player in self.players() {
+            match teams.iter_mut().find(|(team, _)| *team == player.team) {
+                Some((_, players)) => players.push(player),
+                None => teams.push((player.team, vec![player])),
+            }
+        }
+
+        if teams.len() == 1 {
+            let players = teams.pop().map(|(_, players)| players).unwrap_or_default();
+            return players.into_iter().map(|player| vec![player]).collect();
+        }
+        teams.sort_by_key(|(team, _)| *team);
+        teams.into_iter().map(|(_, players)| players).collect()
+    }
+
+    /// Short matchup description, e.g. "PvZ" or "2v2 TP vs ZZ"
+    pub fn matchup(&self) -> String {
+        let teams: Vec<Vec<Race>> = self
+            .teams()
+            .into_iter()
+            .map(|players| players.iter().map(|p| p.race).collect())
+            .collect();
+
+        let letters = |races: &[Race]| races.iter().map(Race::letter).collect::<String>();
+
+        if teams.iter().all(|races| races.len() == 1) {
+            return teams
+                .iter()
+                .map(|races| letters(races))
+                .collect::<Vec<_>>()
+                .join("v");
+        }
+
+        let sizes = teams
+            .iter()
+            .map(|races| races.len().to_string())
+            .collect::<Vec<_>>()
+            .join("v");
+        let races = teams
+            .iter()
+            .map(|races| letters(races))
+            .collect::<Vec<_>>()
+            .join(" vs ");
+        format!("{sizes} {races}")
+    }
+
+    /// Decompresses and yields the frames one by one, so callers that stop
+    /// early do not pay for the rest
+    pub fn frames(&self) -> Frames<'_> {
+        Frames::new(&self.commands)
+    }
+
+    /// Decodes every command once, in order, along with the frame it was
+    /// issued on. Damage found on the way is kept for `warnings`.
+    pub fn each_command(&self, mut f: impl FnMut(u32, PlayerCommand<'_>)) {
+        self.read_commands(|frame_number, commands| {
+            for command in commands {
+                f(frame_number, command);
+            }
+        });
+    }
+
+    /// Like `each_command`, for the commands `player` issued
+    pub fn commands_for(&self, player: &PlayerStruct, mut f: impl FnMut(u32, Command<'_>)) {
+        self.each_command(|frame_number, command| {
+            if command.player_id == player.id {
+                f(frame_number, command.command);
+            }
+        });
+    }
+
+    /// Hands the commands of each frame to `f`, which reads them all, then
+    /// keeps the damage found for `warnings`
+    fn read_commands(&self, mut f: impl FnMut(u32, &mut Commands<'_>)) {
+        let mut warnings = Vec::new();
+        let mut frames = self.frames();
+        for frame in &mut frames {
+            let mut commands = frame.commands();
+            f(frame.frame_number, &mut commands);
+            warnings.extend(commands.error().cloned());
+        }
+        warnings.extend(frames.error().cloned());
+        // Any later read finds the same damage
+        let _ = self.command_warnings.set(warnings);
     }
 
     pub fn chat_messages(&self) -> Vec<ChatMessage> {
         let mut messages = Vec::new();
 
-        for frame in &self.frames {
-            for command in &frame.commands {
-                if command.command_type == 0x5c {
-                    if let Some(chat_msg) = Self::parse_chat_command(
-                        command,
-                        &self.game_info.player_structs,
-                        frame.frame_number,
-                    ) {
-                        messages.push(chat_msg);
-                    }
+        self.each_command(|frame_number, command| {
+            if let Command::Chat { sender, message } = command.command {
+                if let Some(chat_msg) = Self::parse_chat_command(
+                    sender,
+                    message,
+                    &self.game_info.player_structs,
+                    frame_number,
+                    self.clock(),
+                ) {
+                    messages.push(chat_msg);
                 }
             }
-        }
+        });
 
         messages
     }
 
     fn parse_chat_command(
-        command: &Command,
+        sender_id: u8,
+        message_bytes: &[u8],
         players: &[PlayerStruct],
         frame_number: u32,
+        clock: FrameClock,
     ) -> Option<ChatMessage> {
-        if command.command_type != 0x5c {
-            return None;
-        }
-
-        if command.data.len() < 81 {
-            return None;
-        }
-
-        let sender_id = command.data[0];
-        let message_bytes = &command.data[1..81];
-
-        let null_pos = message_bytes.iter().position(|&b| b == 0).unwrap_or(80);
-
-        let message = match std::str::from_utf8(&message_bytes[..null_pos]) {
-            Ok(s) => s.trim().to_string(),
-            Err(_) => String::from_utf8_lossy(&message_bytes[..null_pos])
-                .trim()
-                .to_string(),
-        };
+        let message = text::decode(message_bytes).trim().to_string();
 
         if message.is_empty() {
             return None;
@@ -117,21 +353,88 @@ impl ParsedReplay {
             sender_name,
             message,
             frame_number,
+            timestamp_ms: clock.ms(frame_number),
             sender_id,
         })
     }
 }
 
-fn skip_section(input: &[u8]) -> IResult<&[u8], ()> {
-    let (input, _crc) = le_u32(input)?;
-    let (input, num_chunks) = le_u32(input)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_parser::section::SectionReader;
+
+    /// A replay without a map, `players` being (race, team) and `frames`
+    /// each frame number with its command block
+    pub(crate) fn replay(players: &[(u8, u8)], frames: &[(u32, Vec<u8>)]) -> ParsedReplay {
+        let mut data = vec![0u8; 0x279];
+        for (i, &(race, team)) in players.iter().enumerate() {
+            let o = 0xa1 + i * 36;
+            data[o + 4] = i as u8;
+            data[o + 8] = 2; // human
+            data[o + 9] = race;
+            data[o + 10] = team;
+            data[o + 11] = b'A' + i as u8;
+        }
+        let mut commands = Vec::new();
+        for (frame, block) in frames {
+            commands.extend(frame.to_le_bytes());
+            commands.push(block.len() as u8);
+            commands.extend(block);
+        }
+
+        let reader = SectionReader {
+            format: ReplayFormat::Modern,
+            options: ParseOptions::strict(),
+        };
+        let (_, game_info) =
+            game_info::parse_game_info_section(&stored(&data), reader, &mut Vec::new()).unwrap();
+        let (_, commands) = reader
+            .read_lazy(&stored(&commands), commands.len(), Section::Commands)
+            .unwrap();
+        ParsedReplay {
+            game_info,
+            commands,
+            map: Chk::default(),
+            extended: ExtendedSections::default(),
+            warnings: Vec::new(),
+            command_warnings: OnceCell::new(),
+        }
+    }
+
+    /// A section holding `data` in one uncompressed chunk
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let mut section = Vec::new();
+        section.extend(crc32fast::hash(data).to_le_bytes());
+        section.extend(u32::from(!data.is_empty()).to_le_bytes());
+        if !data.is_empty() {
+            section.extend((data.len() as u32).to_le_bytes());
+            section.extend(data);
+        }
+        section
+    }
+
+    pub(crate) const ZERG: u8 = 0;
+    pub(crate) const TERRAN: u8 = 1;
+    pub(crate) const PROTOSS: u8 = 2;
+
+    #[test]
+    fn one_on_one() {
+        assert_eq!(replay(&[(PROTOSS, 1), (ZERG, 2)], &[]).matchup(), "PvZ");
+    }
 
-    let mut remaining = input;
-    for _ in 0..num_chunks {
-        let (new_remaining, chunk_size) = le_u32(remaining)?;
-        let (new_remaining, _chunk_data) = take(chunk_size)(new_remaining)?;
-        remaining = new_remaining;
+    #[test]
+    fn melee_players_on_one_team_are_a_free_for_all() {
+        let ffa = replay(&[(TERRAN, 0), (ZERG, 0), (PROTOSS, 0)], &[]);
+        assert_eq!(ffa.matchup(), "TvZvP");
     }
 
-    Ok((remaining, ()))
+    #[test]
+    fn team_games_list_sizes_then_races_in_team_order() {
+        let two_on_two = replay(&[(ZERG, 2), (TERRAN, 1), (ZERG, 2), (PROTOSS, 1)], &[]);
+        assert_eq!(two_on_two.matchup(), "2v2 TP vs ZZ");
+
+        let uneven = replay(&[(ZERG, 1), (TERRAN, 2), (PROTOSS, 2)], &[]);
+        assert_eq!(uneven.matchup(), "1v2 Z vs TP");
+    }
 }
@@ -0,0 +1,404 @@
+//! Static Brood War unit, tech, upgrade and order names, indexed by the ids
+//! used in the command stream. Only units, tech and upgrades a player can
+//! build, train or research in a regular game are listed.
+
+pub fn unit_name(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0 => "Marine",
+        1 => "Ghost",
+        2 => "Vulture",
+        3 => "Goliath",
+        5 => "Siege Tank",
+        7 => "SCV",
+        8 => "Wraith",
+        9 => "Science Vessel",
+        11 => "Dropship",
+        12 => "Battlecruiser",
+        13 => "Spider Mine",
+        14 => "Nuclear Missile",
+        30 => "Siege Tank (Siege Mode)",
+        32 => "Firebat",
+        34 => "Medic",
+        35 => "Larva",
+        36 => "Egg",
+        37 => "Zergling",
+        38 => "Hydralisk",
+        39 => "Ultralisk",
+        41 => "Drone",
+        42 => "Overlord",
+        43 => "Mutalisk",
+        44 => "Guardian",
+        45 => "Queen",
+        46 => "Defiler",
+        47 => "Scourge",
+        50 => "Infested Terran",
+        58 => "Valkyrie",
+        59 => "Cocoon",
+        60 => "Corsair",
+        61 => "Dark Templar",
+        62 => "Devourer",
+        63 => "Dark Archon",
+        64 => "Probe",
+        65 => "Zealot",
+        66 => "Dragoon",
+        67 => "High Templar",
+        68 => "Archon",
+        69 => "Shuttle",
+        70 => "Scout",
+        71 => "Arbiter",
+        72 => "Carrier",
+        73 => "Interceptor",
+        83 => "Reaver",
+        84 => "Observer",
+        85 => "Scarab",
+        97 => "Lurker Egg",
+        103 => "Lurker",
+        106 => "Command Center",
+        107 => "Comsat Station",
+        108 => "Nuclear Silo",
+        109 => "Supply Depot",
+        110 => "Refinery",
+        111 => "Barracks",
+        112 => "Academy",
+        113 => "Factory",
+        114 => "Starport",
+        115 => "Control Tower",
+        116 => "Science Facility",
+        117 => "Covert Ops",
+        118 => "Physics Lab",
+        120 => "Machine Shop",
+        122 => "Engineering Bay",
+        123 => "Armory",
+        124 => "Missile Turret",
+        125 => "Bunker",
+        130 => "Infested Command Center",
+        131 => "Hatchery",
+        132 => "Lair",
+        133 => "Hive",
+        134 => "Nydus Canal",
+        135 => "Hydralisk Den",
+        136 => "Defiler Mound",
+        137 => "Greater Spire",
+        138 => "Queen's Nest",
+        139 => "Evolution Chamber",
+        140 => "Ultralisk Cavern",
+        141 => "Spire",
+        142 => "Spawning Pool",
+        143 => "Creep Colony",
+        144 => "Spore Colony",
+        146 => "Sunken Colony",
+        149 => "Extractor",
+        154 => "Nexus",
+        155 => "Robotics Facility",
+        156 => "Pylon",
+        157 => "Assimilator",
+        159 => "Observatory",
+        160 => "Gateway",
+        162 => "Photon Cannon",
+        163 => "Citadel of Adun",
+        164 => "Cybernetics Core",
+        165 => "Templar Archives",
+        166 => "Forge",
+        167 => "Stargate",
+        169 => "Fleet Beacon",
+        170 => "Arbiter Tribunal",
+        171 => "Robotics Support Bay",
+        172 => "Shield Battery",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn tech_name(id: u8) -> Option<&'static str> {
+    let name = match id {
+        0 => "Stim Packs",
+        1 => "Lockdown",
+        2 => "EMP Shockwave",
+        3 => "Spider Mines",
+        4 => "Scanner Sweep",
+        5 => "Tank Siege Mode",
+        6 => "Defensive Matrix",
+        7 => "Irradiate",
+        8 => "Yamato Gun",
+        9 => "Cloaking Field",
+        10 => "Personnel Cloaking",
+        11 => "Burrowing",
+        12 => "Infestation",
+        13 => "Spawn Broodlings",
+        14 => "Dark Swarm",
+        15 => "Plague",
+        16 => "Consume",
+        17 => "Ensnare",
+        18 => "Parasite",
+        19 => "Psionic Storm",
+        20 => "Hallucination",
+        21 => "Recall",
+        22 => "Stasis Field",
+        23 => "Archon Warp",
+        24 => "Restoration",
+        25 => "Disruption Web",
+        27 => "Mind Control",
+        28 => "Dark Archon Meld",
+        29 => "Feedback",
+        30 => "Optical Flare",
+        31 => "Maelstrom",
+        32 => "Lurker Aspect",
+        34 => "Healing",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn upgrade_name(id: u8) -> Option<&'static str> {
+    let name = match id {
+        0 => "Terran Infantry Armor",
+        1 => "Terran Vehicle Plating",
+        2 => "Terran Ship Plating",
+        3 => "Zerg Carapace",
+        4 => "Zerg Flyer Carapace",
+        5 => "Protoss Ground Armor",
+        6 => "Protoss Air Armor",
+        7 => "Terran Infantry Weapons",
+        8 => "Terran Vehicle Weapons",
+        9 => "Terran Ship Weapons",
+        10 => "Zerg Melee Attacks",
+        11 => "Zerg Missile Attacks",
+        12 => "Zerg Flyer Attacks",
+        13 => "Protoss Ground Weapons",
+        14 => "Protoss Air Weapons",
+        15 => "Protoss Plasma Shields",
+        16 => "U-238 Shells",
+        17 => "Ion Thrusters",
+        19 => "Titan Reactor",
+        20 => "Ocular Implants",
+        21 => "Moebius Reactor",
+        22 => "Apollo Reactor",
+        23 => "Colossus Reactor",
+        24 => "Ventral Sacs",
+        25 => "Antennae",
+        26 => "Pneumatized Carapace",
+        27 => "Metabolic Boost",
+        28 => "Adrenal Glands",
+        29 => "Muscular Augments",
+        30 => "Grooved Spines",
+        31 => "Gamete Meiosis",
+        32 => "Metasynaptic Node",
+        33 => "Singularity Charge",
+        34 => "Leg Enhancements",
+        35 => "Scarab Damage",
+        36 => "Reaver Capacity",
+        37 => "Gravitic Drive",
+        38 => "Sensor Array",
+        39 => "Gravitic Boosters",
+        40 => "Khaydarin Amulet",
+        41 => "Apial Sensors",
+        42 => "Gravitic Thrusters",
+        43 => "Carrier Capacity",
+        44 => "Khaydarin Core",
+        47 => "Argus Jewel",
+        49 => "Argus Talisman",
+        51 => "Caduceus Reactor",
+        52 => "Chitinous Plating",
+        53 => "Anabolic Synthesis",
+        54 => "Charon Boosters",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Every order in orders.dat, since commands may carry any of them
+pub fn order_name(id: u8) -> Option<&'static str> {
+    let name = match id {
+        0 => "Die",
+        1 => "Stop",
+        2 => "Guard",
+        3 => "PlayerGuard",
+        4 => "TurretGuard",
+        5 => "BunkerGuard",
+        6 => "Move",
+        7 => "ReaverStop",
+        8 => "Attack1",
+        9 => "Attack2",
+        10 => "AttackUnit",
+        11 => "AttackFixedRange",
+        12 => "AttackTile",
+        13 => "Hover",
+        14 => "AttackMove",
+        15 => "InfestedCommandCenter",
+        16 => "UnusedNothing",
+        17 => "UnusedPowerup",
+        18 => "TowerGuard",
+        19 => "TowerAttack",
+        20 => "VultureMine",
+        21 => "StayInRange",
+        22 => "TurretAttack",
+        23 => "Nothing",
+        24 => "Unused_24",
+        25 => "DroneStartBuild",
+        26 => "DroneBuild",
+        27 => "CastInfestation",
+        28 => "MoveToInfest",
+        29 => "InfestingCommandCenter",
+        30 => "PlaceBuilding",
+        31 => "PlaceProtossBuilding",
+        32 => "CreateProtossBuilding",
+        33 => "ConstructingBuilding",
+        34 => "Repair",
+        35 => "MoveToRepair",
+        36 => "PlaceAddon",
+        37 => "BuildAddon",
+        38 => "Train",
+        39 => "RallyPointUnit",
+        40 => "RallyPointTile",
+        41 => "ZergBirth",
+        42 => "ZergUnitMorph",
+        43 => "ZergBuildingMorph",
+        44 => "IncompleteBuilding",
+        45 => "IncompleteMorphing",
+        46 => "BuildNydusExit",
+        47 => "EnterNydusCanal",
+        48 => "IncompleteWarping",
+        49 => "Follow",
+        50 => "Carrier",
+        51 => "ReaverCarrierMove",
+        52 => "CarrierStop",
+        53 => "CarrierAttack",
+        54 => "CarrierMoveToAttack",
+        55 => "CarrierIgnore2",
+        56 => "CarrierFight",
+        57 => "CarrierHoldPosition",
+        58 => "Reaver",
+        59 => "ReaverAttack",
+        60 => "ReaverMoveToAttack",
+        61 => "ReaverFight",
+        62 => "ReaverHoldPosition",
+        63 => "TrainFighter",
+        64 => "InterceptorAttack",
+        65 => "ScarabAttack",
+        66 => "RechargeShieldsUnit",
+        67 => "RechargeShieldsBattery",
+        68 => "ShieldBattery",
+        69 => "InterceptorReturn",
+        70 => "DroneLand",
+        71 => "BuildingLand",
+        72 => "BuildingLiftOff",
+        73 => "DroneLiftOff",
+        74 => "LiftingOff",
+        75 => "ResearchTech",
+        76 => "Upgrade",
+        77 => "Larva",
+        78 => "SpawningLarva",
+        79 => "Harvest1",
+        80 => "Harvest2",
+        81 => "MoveToGas",
+        82 => "WaitForGas",
+        83 => "HarvestGas",
+        84 => "ReturnGas",
+        85 => "MoveToMinerals",
+        86 => "WaitForMinerals",
+        87 => "MiningMinerals",
+        88 => "Harvest3",
+        89 => "Harvest4",
+        90 => "ReturnMinerals",
+        91 => "Interrupted",
+        92 => "EnterTransport",
+        93 => "PickupIdle",
+        94 => "PickupTransport",
+        95 => "PickupBunker",
+        96 => "Pickup4",
+        97 => "PowerupIdle",
+        98 => "Sieging",
+        99 => "Unsieging",
+        100 => "WatchTarget",
+        101 => "InitCreepGrowth",
+        102 => "SpreadCreep",
+        103 => "StoppingCreepGrowth",
+        104 => "GuardianAspect",
+        105 => "ArchonWarp",
+        106 => "CompletingArchonSummon",
+        107 => "HoldPosition",
+        108 => "QueenHoldPosition",
+        109 => "Cloak",
+        110 => "Decloak",
+        111 => "Unload",
+        112 => "MoveUnload",
+        113 => "FireYamatoGun",
+        114 => "MoveToFireYamatoGun",
+        115 => "CastLockdown",
+        116 => "Burrowing",
+        117 => "Burrowed",
+        118 => "Unburrowing",
+        119 => "CastDarkSwarm",
+        120 => "CastParasite",
+        121 => "CastSpawnBroodlings",
+        122 => "CastEMPShockwave",
+        123 => "NukeWait",
+        124 => "NukeTrain",
+        125 => "NukeLaunch",
+        126 => "NukePaint",
+        127 => "NukeUnit",
+        128 => "CastNuclearStrike",
+        129 => "NukeTrack",
+        130 => "InitializeArbiter",
+        131 => "CloakNearbyUnits",
+        132 => "PlaceMine",
+        133 => "RightClickAction",
+        134 => "SuicideUnit",
+        135 => "SuicideLocation",
+        136 => "SuicideHoldPosition",
+        137 => "CastRecall",
+        138 => "Teleport",
+        139 => "CastScannerSweep",
+        140 => "Scanner",
+        141 => "CastDefensiveMatrix",
+        142 => "CastPsionicStorm",
+        143 => "CastIrradiate",
+        144 => "CastPlague",
+        145 => "CastConsume",
+        146 => "CastEnsnare",
+        147 => "CastStasisField",
+        148 => "CastHallucination",
+        149 => "Hallucination2",
+        150 => "ResetCollision",
+        151 => "ResetHarvestCollision",
+        152 => "Patrol",
+        153 => "CTFCOPInit",
+        154 => "CTFCOPStarted",
+        155 => "CTFCOP2",
+        156 => "ComputerAI",
+        157 => "AtkMoveEP",
+        158 => "HarvestMove",
+        159 => "AIPatrol",
+        160 => "GuardPost",
+        161 => "RescuePassive",
+        162 => "Neutral",
+        163 => "ComputerReturn",
+        164 => "InitializePsiProvider",
+        165 => "SelfDestructing",
+        166 => "Critter",
+        167 => "HiddenGun",
+        168 => "OpenDoor",
+        169 => "CloseDoor",
+        170 => "HideTrap",
+        171 => "RevealTrap",
+        172 => "EnableDoodad",
+        173 => "DisableDoodad",
+        174 => "WarpIn",
+        175 => "Medic",
+        176 => "MedicHeal",
+        177 => "HealMove",
+        178 => "MedicHoldPosition",
+        179 => "MedicHealToIdle",
+        180 => "CastRestoration",
+        181 => "CastDisruptionWeb",
+        182 => "CastMindControl",
+        183 => "DarkArchonMeld",
+        184 => "CastFeedback",
+        185 => "CastOpticalFlare",
+        186 => "CastMaelstrom",
+        187 => "JunkYardDog",
+        188 => "Fatal",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -0,0 +1,184 @@
+//! PKWARE Data Compression Library "explode", used by replays written before
+//! 1.18. Port of Mark Adler's `blast.c` from zlib's contrib directory.
+
+use crate::replay_parser::ParseError;
+
+const MAX_BITS: usize = 13;
+
+/// Compact run-length encoding of the literal code bit lengths
+const LITERAL_LENGTHS: [u8; 98] = [
+    11, 124, 8, 7, 28, 7, 188, 13, 76, 4, 10, 8, 12, 10, 12, 10, 8, 23, 8, 9, 7, 6, 7, 8, 7, 6, 55,
+    8, 23, 24, 12, 11, 7, 9, 11, 12, 6, 7, 22, 5, 7, 24, 6, 11, 9, 6, 7, 22, 7, 11, 38, 7, 9, 8,
+    25, 11, 8, 11, 9, 12, 8, 12, 5, 38, 5, 38, 5, 11, 7, 5, 6, 21, 6, 10, 53, 8, 7, 24, 10, 27, 44,
+    253, 253, 253, 252, 252, 252, 13, 12, 45, 12, 45, 12, 61, 12, 45, 44, 173,
+];
+const LENGTH_LENGTHS: [u8; 6] = [2, 35, 36, 53, 38, 23];
+const DISTANCE_LENGTHS: [u8; 7] = [2, 20, 53, 230, 247, 151, 248];
+
+const LENGTH_BASE: [usize; 16] = [3, 2, 4, 5, 6, 7, 8, 9, 10, 12, 16, 24, 40, 72, 136, 264];
+const LENGTH_EXTRA: [u32; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+
+const END_OF_STREAM: usize = 519;
+
+struct Huffman {
+    count: [usize; MAX_BITS + 1],
+    symbol: Vec<usize>,
+}
+
+impl Huffman {
+    fn new(compact: &[u8]) -> Self {
+        let mut lengths = Vec::new();
+        for &rep in compact {
+            let len = (rep & 15) as usize;
+            let times = (rep >> 4) as usize + 1;
+            lengths.extend(std::iter::repeat_n(len, times));
+        }
+
+        let mut count = [0usize; MAX_BITS + 1];
+        for &len in &lengths {
+            count[len] += 1;
+        }
+
+        let mut offsets = [0usize; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+
+        let mut symbol = vec![0usize; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offsets[len]] = sym;
+                offsets[len] += 1;
+            }
+        }
+
+        Self { count, symbol }
+    }
+}
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, need: u32) -> Result<u32, ParseError> {
+        while self.bit_count < need {
+            let byte = *self
+                .input
+                .get(self.pos)
+                .ok_or_else(|| ParseError::invalid("PKWARE stream ended early"))?;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.pos += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value)
+    }
+
+    /// Codes are stored with their bits inverted, one bit at a time
+    fn decode(&mut self, huffman: &Huffman) -> Result<usize, ParseError> {
+        let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+        for len in 1..=MAX_BITS {
+            code |= (self.bits(1)? ^ 1) as usize;
+            let count = huffman.count[len];
+            if code < first + count {
+                return Ok(huffman.symbol[index + (code - first)]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(ParseError::invalid("Invalid PKWARE code"))
+    }
+}
+
+/// Decompresses a PKWARE DCL imploded buffer
+pub fn explode(input: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let literal_code = Huffman::new(&LITERAL_LENGTHS);
+    let length_code = Huffman::new(&LENGTH_LENGTHS);
+    let distance_code = Huffman::new(&DISTANCE_LENGTHS);
+
+    let mut reader = BitReader {
+        input,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+    };
+
+    let coded_literals = reader.bits(8)?;
+    if coded_literals > 1 {
+        return Err(ParseError::invalid(format!(
+            "Invalid PKWARE literal mode {coded_literals}"
+        )));
+    }
+    let dict = reader.bits(8)?;
+    if !(4..=6).contains(&dict) {
+        return Err(ParseError::invalid(format!(
+            "Invalid PKWARE dictionary size {dict}"
+        )));
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 4);
+    loop {
+        if reader.bits(1)? == 1 {
+            let symbol = reader.decode(&length_code)?;
+            let len = LENGTH_BASE[symbol] + reader.bits(LENGTH_EXTRA[symbol])? as usize;
+            if len == END_OF_STREAM {
+                break;
+            }
+
+            let extra_bits = if len == 2 { 2 } else { dict };
+            let mut dist = reader.decode(&distance_code)? << extra_bits;
+            dist += reader.bits(extra_bits)? as usize;
+            dist += 1;
+            if dist > output.len() {
+                return Err(ParseError::invalid("PKWARE distance too far back"));
+            }
+
+            let start = output.len() - dist;
+            for i in 0..len {
+                output.push(output[start + i]);
+            }
+        } else {
+            let literal = if coded_literals == 1 {
+                reader.decode(&literal_code)? as u8
+            } else {
+                reader.bits(8)? as u8
+            };
+            output.push(literal);
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example from the comments in blast.c
+    const BLAST_EXAMPLE: [u8; 8] = [0x00, 0x04, 0x82, 0x24, 0x25, 0x8f, 0x80, 0x7f];
+
+    #[test]
+    fn explodes_blast_example() {
+        assert_eq!(explode(&BLAST_EXAMPLE).unwrap(), b"AIAIAIAIAIAIA");
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        for len in 0..BLAST_EXAMPLE.len() {
+            assert!(explode(&BLAST_EXAMPLE[..len]).is_err(), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn invalid_header_is_an_error() {
+        assert!(explode(&[0x02, 0x04, 0x00]).is_err());
+        assert!(explode(&[0x00, 0x07, 0x00]).is_err());
+    }
+}
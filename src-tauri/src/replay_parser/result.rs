@@ -0,0 +1,210 @@
+use crate::replay_parser::{Command, ParsedReplay};
+
+/// Why a player left, from the leave game command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    Quit,
+    Defeat,
+    Victory,
+    Finished,
+    Draw,
+    Dropped,
+    Unknown(u8),
+}
+
+impl From<u8> for LeaveReason {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => LeaveReason::Quit,
+            0x02 => LeaveReason::Defeat,
+            0x03 => LeaveReason::Victory,
+            0x04 => LeaveReason::Finished,
+            0x05 => LeaveReason::Draw,
+            0x06 => LeaveReason::Dropped,
+            v => LeaveReason::Unknown(v),
+        }
+    }
+}
+
+impl From<LeaveReason> for u8 {
+    fn from(reason: LeaveReason) -> Self {
+        match reason {
+            LeaveReason::Quit => 0x01,
+            LeaveReason::Defeat => 0x02,
+            LeaveReason::Victory => 0x03,
+            LeaveReason::Finished => 0x04,
+            LeaveReason::Draw => 0x05,
+            LeaveReason::Dropped => 0x06,
+            LeaveReason::Unknown(v) => v,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerResult {
+    pub player_id: u8,
+    pub outcome: Outcome,
+    /// Frame of the player's first leave game command. Whoever saved the
+    /// replay usually has none.
+    pub leave_frame: Option<u32>,
+    pub leave_reason: Option<LeaveReason>,
+}
+
+impl ParsedReplay {
+    /// Who won, inferred from the order players left in: once every team
+    /// but one has left, that team wins and the others lose. Observers and
+    /// games where several teams stay until the end are `Unknown`.
+    pub fn result(&self) -> Vec<PlayerResult> {
+        let mut leaves: Vec<(u8, u32, LeaveReason)> = Vec::new();
+        self.each_command(|frame_number, command| {
+            if let Command::LeaveGame { reason } = command.command {
+                if !leaves.iter().any(|&(id, _, _)| id == command.player_id) {
+                    leaves.push((command.player_id, frame_number, reason.into()));
+                }
+            }
+        });
+        let leave = |player_id: u8| leaves.iter().find(|&&(id, _, _)| id == player_id);
+
+        let teams = self.teams();
+        let mut remaining: Vec<Vec<u8>> = teams
+            .iter()
+            .map(|players| players.iter().map(|p| p.id).collect())
+            .collect();
+        let mut winner = None;
+        for &(player_id, _, reason) in &leaves {
+            if reason == LeaveReason::Victory {
+                if let Some(team) = remaining.iter().position(|team| team.contains(&player_id)) {
+                    winner = Some(team);
+                    break;
+                }
+            }
+            for team in &mut remaining {
+                team.retain(|&id| id != player_id);
+            }
+            let mut alive = remaining.iter().enumerate().filter(|(_, t)| !t.is_empty());
+            if let (Some((team, _)), None) = (alive.next(), alive.next()) {
+                winner = Some(team);
+                break;
+            }
+        }
+
+        let team_of = |player_id: u8| {
+            teams
+                .iter()
+                .position(|players| players.iter().any(|p| p.id == player_id))
+        };
+
+        self.game_info
+            .player_structs
+            .iter()
+            .filter(|p| p.is_active())
+            .map(|player| {
+                let team = team_of(player.id);
+                let outcome = match (team, winner) {
+                    (None, _) => Outcome::Unknown,
+                    (Some(team), Some(winner)) if team == winner => Outcome::Win,
+                    (Some(_), Some(_)) => Outcome::Loss,
+                    // The whole team left without anyone else leaving
+                    (Some(team), None) if remaining[team].is_empty() => Outcome::Loss,
+                    (Some(_), None) => Outcome::Unknown,
+                };
+                let leave = leave(player.id);
+                PlayerResult {
+                    player_id: player.id,
+                    outcome,
+                    leave_frame: leave.map(|&(_, frame, _)| frame),
+                    leave_reason: leave.map(|&(_, _, reason)| reason),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_parser::tests::{replay, PROTOSS, TERRAN, ZERG};
+
+    fn leave(player_id: u8, reason: u8) -> Vec<u8> {
+        vec![player_id, 0x57, reason]
+    }
+
+    fn outcomes(replay: &ParsedReplay) -> Vec<Outcome> {
+        replay.result().iter().map(|r| r.outcome).collect()
+    }
+
+    #[test]
+    fn last_player_standing_wins() {
+        let replay = replay(&[(PROTOSS, 1), (ZERG, 2)], &[(100, leave(1, 1))]);
+        assert_eq!(outcomes(&replay), [Outcome::Win, Outcome::Loss]);
+        let result = replay.result();
+        assert_eq!(result[1].leave_frame, Some(100));
+        assert_eq!(result[1].leave_reason, Some(LeaveReason::Quit));
+        assert_eq!(result[0].leave_frame, None);
+    }
+
+    #[test]
+    fn nobody_leaving_is_unknown() {
+        let replay = replay(&[(PROTOSS, 1), (ZERG, 2)], &[]);
+        assert_eq!(outcomes(&replay), [Outcome::Unknown, Outcome::Unknown]);
+    }
+
+    #[test]
+    fn a_team_wins_once_the_other_team_has_left() {
+        let players = [(ZERG, 1), (TERRAN, 2), (PROTOSS, 1), (ZERG, 2)];
+        let one_left = replay(&players, &[(100, leave(0, 2))]);
+        assert_eq!(
+            outcomes(&one_left),
+            [
+                Outcome::Unknown,
+                Outcome::Unknown,
+                Outcome::Unknown,
+                Outcome::Unknown
+            ]
+        );
+
+        let both_left = replay(&players, &[(100, leave(0, 2)), (200, leave(2, 1))]);
+        assert_eq!(
+            outcomes(&both_left),
+            [Outcome::Loss, Outcome::Win, Outcome::Loss, Outcome::Win]
+        );
+    }
+
+    #[test]
+    fn leaving_with_victory_wins_right_away() {
+        let replay = replay(&[(PROTOSS, 1), (ZERG, 2)], &[(100, leave(0, 3))]);
+        assert_eq!(outcomes(&replay), [Outcome::Win, Outcome::Loss]);
+    }
+
+    #[test]
+    fn free_for_all_players_leave_one_at_a_time() {
+        let players = [(TERRAN, 0), (ZERG, 0), (PROTOSS, 0)];
+        let one_left = replay(&players, &[(100, leave(0, 2))]);
+        assert_eq!(
+            outcomes(&one_left),
+            [Outcome::Loss, Outcome::Unknown, Outcome::Unknown]
+        );
+
+        let two_left = replay(&players, &[(100, leave(0, 2)), (200, leave(2, 2))]);
+        assert_eq!(
+            outcomes(&two_left),
+            [Outcome::Loss, Outcome::Win, Outcome::Loss]
+        );
+    }
+
+    #[test]
+    fn only_the_first_leave_counts() {
+        let replay = replay(
+            &[(PROTOSS, 1), (ZERG, 2)],
+            &[(100, [leave(1, 1), leave(1, 6)].concat())],
+        );
+        assert_eq!(replay.result()[1].leave_reason, Some(LeaveReason::Quit));
+    }
+}
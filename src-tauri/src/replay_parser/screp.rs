@@ -0,0 +1,643 @@
+//! The JSON layout of the `screp` tool (github.com/icza/screp), so existing
+//! tooling can read our output: `Header`, `Commands` and `Computed`.
+
+use crate::replay_parser::{
+    names, text, Command, Engine, GameSpeed, GameType, HotkeyAction, LeaveReason, Outcome,
+    ParsedReplay, PlayerStruct, PlayerType, Race,
+};
+
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepReplay {
+    pub header: ScrepHeader,
+    pub commands: ScrepCommands,
+    pub computed: ScrepComputed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepHeader {
+    pub engine: ScrepEnum,
+    pub frames: u32,
+    /// RFC 3339, e.g. "2024-05-01T18:30:00Z"
+    pub start_time: String,
+    pub title: String,
+    pub map_width: u16,
+    pub map_height: u16,
+    pub avail_slots_count: u8,
+    pub speed: ScrepEnum,
+    #[serde(rename = "Type")]
+    pub game_type: ScrepEnum,
+    pub sub_type: u16,
+    pub host: String,
+    pub map: String,
+    pub players: Vec<ScrepPlayer>,
+}
+
+/// screp's named enums: a display name, the stored id and sometimes a
+/// short name
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepEnum {
+    pub name: String,
+    #[serde(rename = "ID")]
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepPlayer {
+    #[serde(rename = "SlotID")]
+    pub slot_id: u16,
+    #[serde(rename = "ID")]
+    pub id: u8,
+    #[serde(rename = "Type")]
+    pub player_type: ScrepEnum,
+    pub race: ScrepRace,
+    pub team: u8,
+    pub name: String,
+    pub color: Option<ScrepColor>,
+    pub observer: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepRace {
+    pub name: String,
+    #[serde(rename = "ID")]
+    pub id: u8,
+    pub short_name: String,
+    /// A character code, as screp stores it
+    pub letter: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepColor {
+    pub name: String,
+    #[serde(rename = "ID")]
+    pub id: u32,
+    #[serde(rename = "RGB")]
+    pub rgb: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepCommands {
+    pub cmds: Vec<ScrepCommand>,
+    /// Always empty; commands that fail to parse end their frame instead
+    pub parse_err_cmds: Vec<ScrepCommand>,
+}
+
+/// One command. Only the fields that apply to its type are written.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepCommand {
+    pub frame: u32,
+    #[serde(rename = "PlayerID")]
+    pub player_id: u8,
+    #[serde(rename = "Type")]
+    pub command_type: ScrepEnum,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_tags: Option<Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<ScrepEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<ScrepPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_tag: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<ScrepEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotkey_type: Option<ScrepEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<ScrepEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tech: Option<ScrepEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade: Option<ScrepEnum>,
+    #[serde(rename = "SenderSlotID", skip_serializing_if = "Option::is_none")]
+    pub sender_slot_id: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<ScrepEnum>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepPoint {
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepComputed {
+    pub leave_game_cmds: Vec<ScrepCommand>,
+    pub chat_cmds: Vec<ScrepCommand>,
+    /// 0 when unknown
+    pub winner_team: u8,
+    pub player_descs: Vec<ScrepPlayerDesc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScrepPlayerDesc {
+    #[serde(rename = "PlayerID")]
+    pub player_id: u8,
+    pub last_cmd_frame: u32,
+    pub cmd_count: u32,
+    #[serde(rename = "APM")]
+    pub apm: u32,
+    pub effective_cmd_count: u32,
+    #[serde(rename = "EAPM")]
+    pub eapm: u32,
+    pub start_location: Option<ScrepPoint>,
+}
+
+impl ParsedReplay {
+    /// The replay in screp's JSON layout
+    pub fn to_screp(&self) -> ScrepReplay {
+        let mut cmds = Vec::new();
+        self.each_command(|frame_number, command| {
+            cmds.push(screp_command(
+                frame_number,
+                command.player_id,
+                command.command,
+            ));
+        });
+
+        let leave_game_cmds = cmds
+            .iter()
+            .filter(|c| c.reason.is_some())
+            .cloned()
+            .collect();
+        let chat_cmds = cmds
+            .iter()
+            .filter(|c| c.message.is_some())
+            .cloned()
+            .collect();
+
+        ScrepReplay {
+            header: self.screp_header(),
+            commands: ScrepCommands {
+                cmds,
+                parse_err_cmds: Vec::new(),
+            },
+            computed: ScrepComputed {
+                leave_game_cmds,
+                chat_cmds,
+                winner_team: self.winner_team(),
+                player_descs: self.screp_player_descs(),
+            },
+        }
+    }
+
+    /// The team of the winning players, 0 when unknown
+    fn winner_team(&self) -> u8 {
+        self.result()
+            .iter()
+            .find(|r| r.outcome == Outcome::Win)
+            .and_then(|r| self.player(r.player_id))
+            .map_or(0, |p| self.team_number(p))
+    }
+
+    /// `PlayerStruct::team`, except that in a free-for-all, where everyone
+    /// is on the same team, each player is their own team numbered from 1,
+    /// as in `ParsedReplay::teams`
+    fn team_number(&self, player: &PlayerStruct) -> u8 {
+        if !self.players().all(|p| p.team == player.team) {
+            return player.team;
+        }
+        self.players()
+            .position(|p| p.id == player.id)
+            .map_or(player.team, |i| i as u8 + 1)
+    }
+
+    fn screp_header(&self) -> ScrepHeader {
+        let info = &self.game_info;
+        ScrepHeader {
+            engine: engine(info.engine),
+            frames: info.frames,
+            start_time: rfc3339(info.start_time),
+            title: info.title.clone(),
+            map_width: info.map_width,
+            map_height: info.map_height,
+            avail_slots_count: info.available_slots,
+            speed: speed(info.game_speed),
+            game_type: game_type(info.game_type),
+            sub_type: info.game_sub_type,
+            host: info.host_name.clone(),
+            map: info.map_name.clone(),
+            players: info
+                .player_structs
+                .iter()
+                .filter(|p| p.is_active())
+                .map(screp_player)
+                .collect(),
+        }
+    }
+
+    fn screp_player_descs(&self) -> Vec<ScrepPlayerDesc> {
+        self.apm()
+            .into_iter()
+            .map(|apm| {
+                let start_location = self.player(apm.player_id).and_then(|player| {
+                    self.map
+                        .start_locations
+                        .iter()
+                        .find(|l| u16::from(l.owner) == player.slot_id)
+                        .map(|l| ScrepPoint { x: l.x, y: l.y })
+                });
+                ScrepPlayerDesc {
+                    player_id: apm.player_id,
+                    last_cmd_frame: apm.last_frame,
+                    cmd_count: apm.actions,
+                    apm: apm.apm,
+                    effective_cmd_count: apm.effective_actions,
+                    eapm: apm.eapm,
+                    start_location,
+                }
+            })
+            .collect()
+    }
+}
+
+fn named(name: &str, id: u32) -> ScrepEnum {
+    ScrepEnum {
+        name: name.to_string(),
+        id,
+        short_name: None,
+    }
+}
+
+fn short_named(name: &str, id: u32, short_name: &str) -> ScrepEnum {
+    ScrepEnum {
+        short_name: Some(short_name.to_string()),
+        ..named(name, id)
+    }
+}
+
+fn engine(engine: Engine) -> ScrepEnum {
+    match engine {
+        Engine::StarCraft => short_named("StarCraft", 0, "SC"),
+        Engine::BroodWar => short_named("Brood War", 1, "BW"),
+        Engine::Unknown(id) => short_named("Unknown", id.into(), "Unk"),
+    }
+}
+
+fn speed(speed: GameSpeed) -> ScrepEnum {
+    let (name, id) = match speed {
+        GameSpeed::Slowest => ("Slowest", 0),
+        GameSpeed::Slower => ("Slower", 1),
+        GameSpeed::Slow => ("Slow", 2),
+        GameSpeed::Normal => ("Normal", 3),
+        GameSpeed::Fast => ("Fast", 4),
+        GameSpeed::Faster => ("Faster", 5),
+        GameSpeed::Fastest => ("Fastest", 6),
+        GameSpeed::Unknown(id) => ("Unknown", id),
+    };
+    named(name, id.into())
+}
+
+fn game_type(game_type: GameType) -> ScrepEnum {
+    let (name, short_name) = match game_type {
+        GameType::None => ("None", "None"),
+        GameType::Custom => ("Custom", "Custom"),
+        GameType::Melee => ("Melee", "Melee"),
+        GameType::FreeForAll => ("Free For All", "FFA"),
+        GameType::OneOnOne => ("One on One", "1on1"),
+        GameType::CaptureTheFlag => ("Capture The Flag", "CTF"),
+        GameType::Greed => ("Greed", "Greed"),
+        GameType::Slaughter => ("Slaughter", "Slaughter"),
+        GameType::SuddenDeath => ("Sudden Death", "Sudden Death"),
+        GameType::Ladder => ("Ladder", "Ladder"),
+        GameType::UseMapSettings => ("Use map settings", "UMS"),
+        GameType::TeamMelee => ("Team Melee", "TM"),
+        GameType::TeamFreeForAll => ("Team Free For All", "TFFA"),
+        GameType::TeamCaptureTheFlag => ("Team Capture The Flag", "TCTF"),
+        GameType::TopVsBottom => ("Top vs Bottom", "TvB"),
+        GameType::IronManLadder => ("Iron Man Ladder", "IML"),
+        GameType::Unknown(_) => ("Unknown", "Unk"),
+    };
+    short_named(name, u16::from(game_type).into(), short_name)
+}
+
+fn player_type(player_type: PlayerType) -> ScrepEnum {
+    let (name, id) = match player_type {
+        PlayerType::Inactive => ("Inactive", 0),
+        PlayerType::Computer => ("Computer", 1),
+        PlayerType::Human => ("Human", 2),
+        PlayerType::RescuePassive => ("Rescue Passive", 3),
+        PlayerType::Open => ("Open", 6),
+        PlayerType::Neutral => ("Neutral", 7),
+        PlayerType::Closed => ("Closed", 8),
+        PlayerType::Unknown(id) => ("Unknown", id),
+    };
+    named(name, id.into())
+}
+
+fn race(race: Race) -> ScrepRace {
+    let (name, id, short_name) = match race {
+        Race::Zerg => ("Zerg", 0, "zerg"),
+        Race::Terran => ("Terran", 1, "ran"),
+        Race::Protoss => ("Protoss", 2, "toss"),
+        Race::Unknown => ("Unknown", 0xff, "unk"),
+    };
+    ScrepRace {
+        name: name.to_string(),
+        id,
+        short_name: short_name.to_string(),
+        letter: race.letter().into(),
+    }
+}
+
+/// The standard player colors; custom and extended colors are left out
+fn color(id: u32) -> Option<ScrepColor> {
+    let (name, rgb) = match id {
+        0 => ("Red", 0xf40404),
+        1 => ("Blue", 0x0c48cc),
+        2 => ("Teal", 0x2cb494),
+        3 => ("Purple", 0x88409c),
+        4 => ("Orange", 0xf88c14),
+        5 => ("Brown", 0x703014),
+        6 => ("White", 0xcce0d0),
+        7 => ("Yellow", 0xfcfc38),
+        _ => return None,
+    };
+    Some(ScrepColor {
+        name: name.to_string(),
+        id,
+        rgb,
+    })
+}
+
+fn screp_player(player: &PlayerStruct) -> ScrepPlayer {
+    ScrepPlayer {
+        slot_id: player.slot_id,
+        id: player.id,
+        player_type: player_type(player.player_type),
+        race: race(player.race),
+        team: player.team,
+        name: player.name.clone(),
+        color: player.color.and_then(color),
+        observer: player.is_observer,
+    }
+}
+
+fn unit(id: u16) -> ScrepEnum {
+    named(names::unit_name(id).unwrap_or("Unknown"), id.into())
+}
+
+fn unit_order(id: u8) -> ScrepEnum {
+    named(names::order_name(id).unwrap_or("Unknown"), id.into())
+}
+
+fn screp_command(frame: u32, player_id: u8, command: Command) -> ScrepCommand {
+    let mut cmd = ScrepCommand {
+        frame,
+        player_id,
+        command_type: named(command_name(&command), command.opcode().into()),
+        unit_tags: None,
+        order: None,
+        pos: None,
+        unit_tag: None,
+        unit: None,
+        queued: None,
+        hotkey_type: None,
+        group: None,
+        speed: None,
+        tech: None,
+        upgrade: None,
+        sender_slot_id: None,
+        message: None,
+        reason: None,
+    };
+
+    match command {
+        Command::Select(tags)
+        | Command::ShiftSelect(tags)
+        | Command::ShiftDeselect(tags)
+        | Command::Select121(tags)
+        | Command::ShiftSelect121(tags)
+        | Command::ShiftDeselect121(tags) => cmd.unit_tags = Some(tags.iter().collect()),
+        Command::Build {
+            order,
+            x,
+            y,
+            unit_type,
+        } => {
+            cmd.order = Some(unit_order(order));
+            cmd.pos = Some(ScrepPoint { x, y });
+            cmd.unit = Some(unit(unit_type));
+        }
+        Command::GameSpeed(id) => cmd.speed = Some(speed(id.into())),
+        Command::Hotkey { action, group } => {
+            cmd.hotkey_type = Some(match action {
+                HotkeyAction::Assign => named("Assign", 0),
+                HotkeyAction::Select => named("Select", 1),
+                HotkeyAction::Add => named("Add", 2),
+                HotkeyAction::Unknown(id) => named("Unknown", id.into()),
+            });
+            cmd.group = Some(group);
+        }
+        Command::RightClick {
+            x,
+            y,
+            target,
+            unit_type,
+            queued,
+        }
+        | Command::RightClick121 {
+            x,
+            y,
+            target,
+            unit_type,
+            queued,
+        } => {
+            cmd.pos = Some(ScrepPoint { x, y });
+            cmd.unit_tag = Some(target);
+            cmd.unit = Some(unit(unit_type));
+            cmd.queued = Some(queued);
+        }
+        Command::TargetedOrder {
+            x,
+            y,
+            target,
+            unit_type,
+            order,
+            queued,
+        }
+        | Command::TargetedOrder121 {
+            x,
+            y,
+            target,
+            unit_type,
+            order,
+            queued,
+        } => {
+            cmd.pos = Some(ScrepPoint { x, y });
+            cmd.unit_tag = Some(target);
+            cmd.unit = Some(unit(unit_type));
+            cmd.order = Some(unit_order(order));
+            cmd.queued = Some(queued);
+        }
+        Command::Stop { queued }
+        | Command::ReturnCargo { queued }
+        | Command::Cloak { queued }
+        | Command::Decloak { queued }
+        | Command::Unsiege { queued }
+        | Command::Siege { queued }
+        | Command::UnloadAll { queued }
+        | Command::HoldPosition { queued }
+        | Command::Burrow { queued }
+        | Command::Unburrow { queued } => cmd.queued = Some(queued),
+        Command::Train(unit_type)
+        | Command::CancelTrain(unit_type)
+        | Command::UnitMorph(unit_type)
+        | Command::BuildingMorph(unit_type) => cmd.unit = Some(unit(unit_type)),
+        Command::Unload(tag) | Command::Unload121(tag) => cmd.unit_tag = Some(tag),
+        Command::Lift { x, y } | Command::MinimapPing { x, y } => {
+            cmd.pos = Some(ScrepPoint { x, y })
+        }
+        Command::Research(id) => {
+            cmd.tech = Some(named(names::tech_name(id).unwrap_or("Unknown"), id.into()))
+        }
+        Command::Upgrade(id) => {
+            cmd.upgrade = Some(named(
+                names::upgrade_name(id).unwrap_or("Unknown"),
+                id.into(),
+            ))
+        }
+        Command::Chat { sender, message } => {
+            cmd.sender_slot_id = Some(sender);
+            cmd.message = Some(text::decode(message));
+        }
+        Command::LeaveGame { reason } => {
+            let name = match LeaveReason::from(reason) {
+                LeaveReason::Unknown(_) => "Unknown".to_string(),
+                known => format!("{known:?}"),
+            };
+            cmd.reason = Some(named(&name, reason.into()));
+        }
+        _ => {}
+    }
+
+    cmd
+}
+
+/// The command names screp uses
+fn command_name(command: &Command) -> &'static str {
+    match command {
+        Command::KeepAlive => "Keep Alive",
+        Command::SaveGame { .. } => "Save Game",
+        Command::LoadGame { .. } => "Load Game",
+        Command::RestartGame => "Restart Game",
+        Command::Select(_) | Command::Select121(_) => "Select",
+        Command::ShiftSelect(_) | Command::ShiftSelect121(_) => "Shift Select",
+        Command::ShiftDeselect(_) | Command::ShiftDeselect121(_) => "Shift Deselect",
+        Command::Build { .. } => "Build",
+        Command::Vision(_) => "Vision",
+        Command::Alliance(_) => "Alliance",
+        Command::GameSpeed(_) => "Game Speed",
+        Command::Pause => "Pause",
+        Command::Resume => "Resume",
+        Command::Cheat(_) => "Cheat",
+        Command::Hotkey { .. } => "Hotkey",
+        Command::RightClick { .. } | Command::RightClick121 { .. } => "Right Click",
+        Command::TargetedOrder { .. } | Command::TargetedOrder121 { .. } => "Targeted Order",
+        Command::CancelBuild => "Cancel Build",
+        Command::CancelMorph => "Cancel Morph",
+        Command::Stop { .. } => "Stop",
+        Command::CarrierStop => "Carrier Stop",
+        Command::ReaverStop => "Reaver Stop",
+        Command::OrderNothing => "Order Nothing",
+        Command::ReturnCargo { .. } => "Return Cargo",
+        Command::Train(_) => "Train",
+        Command::CancelTrain(_) => "Cancel Train",
+        Command::Cloak { .. } => "Cloak",
+        Command::Decloak { .. } => "Decloak",
+        Command::UnitMorph(_) => "Unit Morph",
+        Command::Unsiege { .. } => "Unsiege",
+        Command::Siege { .. } => "Siege",
+        Command::TrainFighter => "Train Fighter",
+        Command::UnloadAll { .. } => "Unload All",
+        Command::Unload(_) | Command::Unload121(_) => "Unload",
+        Command::MergeArchon => "Merge Archon",
+        Command::HoldPosition { .. } => "Hold Position",
+        Command::Burrow { .. } => "Burrow",
+        Command::Unburrow { .. } => "Unburrow",
+        Command::CancelNuke => "Cancel Nuke",
+        Command::Lift { .. } => "Lift Off",
+        Command::Research(_) => "Tech",
+        Command::CancelResearch => "Cancel Tech",
+        Command::Upgrade(_) => "Upgrade",
+        Command::CancelUpgrade => "Cancel Upgrade",
+        Command::CancelAddon => "Cancel Addon",
+        Command::BuildingMorph(_) => "Building Morph",
+        Command::Stim => "Stim",
+        Command::Sync(_) => "Sync",
+        Command::VoiceEnable => "Voice Enable",
+        Command::VoiceDisable => "Voice Disable",
+        Command::VoiceSquelch(_) => "Voice Squelch",
+        Command::VoiceUnsquelch(_) => "Voice Unsquelch",
+        Command::StartGame => "[Lobby] Start Game",
+        Command::DownloadPercentage(_) => "[Lobby] Download Percentage",
+        Command::ChangeGameSlot(_) => "[Lobby] Change Game Slot",
+        Command::NewNetPlayer(_) => "[Lobby] New Net Player",
+        Command::JoinedGame(_) => "[Lobby] Joined Game",
+        Command::ChangeRace { .. } => "[Lobby] Change Race",
+        Command::TeamGameTeam(_) => "[Lobby] Team Game Team",
+        Command::UmsTeam(_) => "[Lobby] UMS Team",
+        Command::MeleeTeam { .. } => "[Lobby] Melee Team",
+        Command::SwapPlayers { .. } => "[Lobby] Swap Players",
+        Command::SavedData(_) => "[Lobby] Saved Data",
+        Command::BriefingStart => "Briefing Start",
+        Command::Latency(_) => "Latency",
+        Command::ReplaySpeed(_) => "Replay Speed",
+        Command::LeaveGame { .. } => "Leave Game",
+        Command::MinimapPing { .. } => "Minimap Ping",
+        Command::MergeDarkArchon => "Merge Dark Archon",
+        Command::MakeGamePublic => "Make Game Public",
+        Command::Chat { .. } => "Chat",
+        Command::Unknown { .. } => "Unknown",
+    }
+}
+
+/// Formats a time as UTC, e.g. "2024-05-01T18:30:00Z"
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
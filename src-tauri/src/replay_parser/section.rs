@@ -0,0 +1,445 @@
+use crate::replay_parser::{error::Location, header::ReplayFormat, pkware, ParseError};
+
+use nom::{bytes::complete::take, number::complete::le_u32};
+use std::{borrow::Cow, fmt};
+
+/// Section data is split into chunks that decompress to at most this size
+pub const CHUNK_SIZE: usize = 8192;
+/// Checksum and chunk count
+const SECTION_HEADER_SIZE: usize = 8;
+
+/// How forgiving the parser is with damaged replays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Compare each section's checksum against its decompressed data
+    pub verify_checksums: bool,
+    /// Fail on chunks that do not decompress instead of taking them as
+    /// uncompressed data. Lenient mode reports them as damage too.
+    pub strict: bool,
+    /// Keep everything read before a failure after the game info section,
+    /// and report the failure in `ParsedReplay::warnings`. Checksum
+    /// mismatches are reported there too, and the data kept.
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    /// Checksums verified and nothing guessed
+    pub fn strict() -> Self {
+        Self {
+            verify_checksums: true,
+            strict: true,
+            lenient: false,
+        }
+    }
+
+    /// Checksums verified, and problems reported as warnings
+    pub fn lenient() -> Self {
+        Self {
+            verify_checksums: true,
+            strict: false,
+            lenient: true,
+        }
+    }
+}
+
+/// Identifies a section in errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    ReplayId,
+    GameInfo,
+    CommandsSize,
+    Commands,
+    MapSize,
+    Map,
+    Extended([u8; 4]),
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::ReplayId => write!(f, "replay id"),
+            Section::GameInfo => write!(f, "game info"),
+            Section::CommandsSize => write!(f, "commands size"),
+            Section::Commands => write!(f, "commands"),
+            Section::MapSize => write!(f, "map size"),
+            Section::Map => write!(f, "map"),
+            Section::Extended(tag) => write!(f, "{}", String::from_utf8_lossy(tag)),
+        }
+    }
+}
+
+/// Reads sections of one replay, given its format and the parse options
+#[derive(Debug, Clone, Copy)]
+pub struct SectionReader {
+    pub format: ReplayFormat,
+    pub options: ParseOptions,
+}
+
+impl SectionReader {
+    /// Reads a section of `size` decompressed bytes: a checksum, the number
+    /// of chunks, then each chunk prefixed by its length. Pass `usize::MAX`
+    /// when the size is not known up front.
+    ///
+    /// Error offsets are relative to `input`. Checksum mismatches are added
+    /// to `warnings` in lenient mode.
+    pub fn read<'a>(
+        &self,
+        input: &'a [u8],
+        size: usize,
+        section: Section,
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<(&'a [u8], Vec<u8>), ParseError> {
+        let (crc, num_chunks, input) = read_section_header(input, section)?;
+
+        // Both counts come from the file; do not trust them past the input
+        let capacity = size
+            .min((num_chunks as usize).saturating_mul(CHUNK_SIZE))
+            .min(input.len());
+        let mut data = Vec::with_capacity(capacity);
+        let mut chunks = self.chunks(input, num_chunks, size, section);
+        for chunk in &mut chunks {
+            data.extend_from_slice(&chunk?);
+        }
+
+        self.verify_checksum(section, crc, &data, warnings)?;
+
+        Ok((chunks.input, data))
+    }
+
+    /// Locates a section without decompressing it, so it can be read chunk
+    /// by chunk later. In lenient mode a section cut short keeps its
+    /// complete chunks and the problem is kept in `LazySection::error`.
+    pub fn read_lazy<'a>(
+        &self,
+        input: &'a [u8],
+        size: usize,
+        section: Section,
+    ) -> Result<(&'a [u8], LazySection), ParseError> {
+        let start = input;
+        let (_, num_chunks, mut input) = read_section_header(input, section)?;
+
+        let mut complete = 0;
+        let mut error = None;
+        for _ in 0..num_chunks {
+            let chunk = le_u32(input).and_then(|(rest, chunk_size)| take(chunk_size)(rest));
+            match chunk {
+                Ok((rest, _)) => {
+                    input = rest;
+                    complete += 1;
+                }
+                Err(e) => {
+                    let e = ParseError::from(e)
+                        .in_section(section)
+                        .offset_by(start.len() - input.len());
+                    if !self.options.lenient {
+                        return Err(e);
+                    }
+                    error = Some(e);
+                    input = &input[input.len()..];
+                    break;
+                }
+            }
+        }
+
+        let raw = &start[..start.len() - input.len()];
+        Ok((
+            input,
+            LazySection {
+                reader: *self,
+                section,
+                size,
+                num_chunks: complete,
+                raw: raw.to_vec(),
+                error,
+            },
+        ))
+    }
+
+    fn chunks<'a>(
+        &self,
+        input: &'a [u8],
+        num_chunks: u32,
+        size: usize,
+        section: Section,
+    ) -> Chunks<'a> {
+        Chunks {
+            reader: *self,
+            section,
+            input,
+            remaining: num_chunks,
+            size_known: size != usize::MAX,
+            size_left: size,
+            offset: SECTION_HEADER_SIZE,
+        }
+    }
+
+    /// Reads the 4 byte section that precedes variable-length sections and
+    /// holds their decompressed size
+    pub fn read_size<'a>(
+        &self,
+        input: &'a [u8],
+        section: Section,
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<(&'a [u8], u32), ParseError> {
+        let (input, data) = self.read(input, 4, section, warnings)?;
+        let (_, size) = le_u32(data.as_slice()).map_err(|e| {
+            ParseError::from(e)
+                .in_section(section)
+                .offset_by(SECTION_HEADER_SIZE)
+        })?;
+        Ok((input, size))
+    }
+
+    /// Sections carry a CRC32 of their decompressed data. A mismatch is an
+    /// error, or a warning in lenient mode, where the data is still used.
+    pub fn verify_checksum(
+        &self,
+        section: Section,
+        expected: u32,
+        data: &[u8],
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<(), ParseError> {
+        if !self.options.verify_checksums {
+            return Ok(());
+        }
+        self.compare_checksum(section, expected, crc32fast::hash(data), warnings)
+    }
+
+    fn compare_checksum(
+        &self,
+        section: Section,
+        expected: u32,
+        actual: u32,
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<(), ParseError> {
+        if actual == expected {
+            return Ok(());
+        }
+        let error = ParseError::ChecksumMismatch {
+            section,
+            expected,
+            actual,
+        };
+        if self.options.lenient {
+            warnings.push(error);
+            return Ok(());
+        }
+        Err(error)
+    }
+
+    /// Chunks that would not shrink are stored as-is. They are recognized
+    /// by their size, or for modern sections of unknown size by not
+    /// starting with a zlib header.
+    fn decompress_chunk<'a>(
+        &self,
+        chunk: &'a [u8],
+        expected: usize,
+        size_known: bool,
+        section: Section,
+    ) -> Result<Cow<'a, [u8]>, ParseError> {
+        let stored = if size_known {
+            chunk.len() == expected
+        } else {
+            self.format == ReplayFormat::Modern && !has_zlib_header(chunk)
+        };
+        if stored {
+            return Ok(Cow::Borrowed(chunk));
+        }
+
+        let decompressed = match self.format {
+            ReplayFormat::Modern => decompress_zlib(chunk).map_err(|e| e.to_string()),
+            ReplayFormat::Legacy => pkware::explode(chunk).map_err(|e| e.to_string()),
+        };
+
+        match decompressed {
+            Ok(data) => Ok(Cow::Owned(data)),
+            Err(e) if self.options.strict || self.options.lenient => Err(ParseError::invalid(
+                format!("Failed to decompress chunk: {e}"),
+            )
+            .in_section(section)),
+            // assume uncompressed, as nothing asked for damage to be reported
+            Err(_) => Ok(Cow::Borrowed(chunk)),
+        }
+    }
+}
+
+/// A section kept in its compressed form
+#[derive(Debug, Clone)]
+pub struct LazySection {
+    reader: SectionReader,
+    section: Section,
+    size: usize,
+    /// Chunks available in `raw`, fewer than stored when cut short
+    num_chunks: u32,
+    /// The section as stored in the replay, checksum and chunk count included
+    raw: Vec<u8>,
+    error: Option<ParseError>,
+}
+
+impl LazySection {
+    /// A section with no data, for when it could not be located
+    pub fn empty(reader: SectionReader, section: Section) -> Self {
+        Self {
+            reader,
+            section,
+            size: 0,
+            num_chunks: 0,
+            raw: vec![0; SECTION_HEADER_SIZE],
+            error: None,
+        }
+    }
+
+    /// Why the section was cut short, in lenient mode
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Moves error offsets relative to the section to ones relative to
+    /// `base`
+    pub fn offset_by(mut self, base: usize) -> Self {
+        self.error = self.error.map(|e| e.offset_by(base));
+        self
+    }
+
+    /// Decompresses the section one chunk at a time
+    pub fn chunks(&self) -> Chunks<'_> {
+        self.reader.chunks(
+            &self.raw[SECTION_HEADER_SIZE..],
+            self.num_chunks,
+            self.size,
+            self.section,
+        )
+    }
+
+    /// Decompresses the whole section at once. Checksum mismatches are
+    /// added to `warnings` in lenient mode.
+    pub fn decompress(&self, warnings: &mut Vec<ParseError>) -> Result<Vec<u8>, ParseError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let (_, data) = self
+            .reader
+            .read(&self.raw, self.size, self.section, warnings)?;
+        Ok(data)
+    }
+
+    /// The CRC32 of the decompressed data, as stored
+    pub fn checksum(&self) -> u32 {
+        u32::from_le_bytes(self.raw[..4].try_into().expect("read in read_lazy"))
+    }
+
+    /// Runs the checks the parse options ask for without keeping the data.
+    /// Checksum mismatches are added to `warnings` in lenient mode.
+    pub fn check(&self, warnings: &mut Vec<ParseError>) -> Result<(), ParseError> {
+        let options = self.reader.options;
+        if !options.verify_checksums && !options.strict {
+            return Ok(());
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        for chunk in self.chunks() {
+            hasher.update(&chunk?);
+        }
+        if !options.verify_checksums {
+            return Ok(());
+        }
+        self.reader
+            .compare_checksum(self.section, self.checksum(), hasher.finalize(), warnings)
+    }
+
+    /// The mismatch between `actual` and the stored checksum, if the options
+    /// ask for checksums to be verified
+    pub fn checksum_mismatch(&self, actual: u32) -> Option<ParseError> {
+        let expected = self.checksum();
+        (self.reader.options.verify_checksums && actual != expected).then_some(
+            ParseError::ChecksumMismatch {
+                section: self.section,
+                expected,
+                actual,
+            },
+        )
+    }
+}
+
+/// Iterator over the decompressed chunks of a section. Stops after the first
+/// error.
+pub struct Chunks<'a> {
+    reader: SectionReader,
+    section: Section,
+    input: &'a [u8],
+    remaining: u32,
+    size_known: bool,
+    size_left: usize,
+    /// Offset of the next chunk from the start of the section
+    offset: usize,
+}
+
+impl<'a> Chunks<'a> {
+    fn next_chunk(&mut self) -> Result<Cow<'a, [u8]>, ParseError> {
+        let (input, chunk_size) = le_u32(self.input)?;
+        let (input, chunk) = take(chunk_size)(input)?;
+        self.input = input;
+        self.offset += 4 + chunk.len();
+
+        let expected = self.size_left.min(CHUNK_SIZE);
+        let data = self
+            .reader
+            .decompress_chunk(chunk, expected, self.size_known, self.section)?;
+        self.size_left -= data.len().min(self.size_left);
+        Ok(data)
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Cow<'a, [u8]>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let offset = self.offset;
+        let chunk = self
+            .next_chunk()
+            .map_err(|e| e.in_section(self.section).offset_by(offset));
+        if chunk.is_err() {
+            self.remaining = 0;
+        }
+        Some(chunk)
+    }
+}
+
+fn read_section_header(input: &[u8], section: Section) -> Result<(u32, u32, &[u8]), ParseError> {
+    let truncated = || {
+        ParseError::Truncated(Location {
+            section: Some(section),
+            offset: Some(0),
+            ..Location::default()
+        })
+    };
+    let (input, crc) =
+        le_u32(input).map_err(|_: nom::Err<nom::error::Error<&[u8]>>| truncated())?;
+    let (input, num_chunks) =
+        le_u32(input).map_err(|_: nom::Err<nom::error::Error<&[u8]>>| truncated())?;
+    Ok((crc, num_chunks, input))
+}
+
+/// Whether a chunk starts like a zlib stream: deflate, with a valid header
+/// check
+pub fn has_zlib_header(chunk: &[u8]) -> bool {
+    match chunk {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn decompress_zlib(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    let mut decoder = ZlibDecoder::new(data);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
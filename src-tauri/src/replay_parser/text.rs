@@ -0,0 +1,81 @@
+//! Strings as stored in replays. Current clients write UTF-8, older ones
+//! wrote the system code page: CP949 for Korean clients, Windows-1252 for
+//! most others.
+
+use encoding_rs::{EUC_KR, WINDOWS_1252};
+use std::borrow::Cow;
+
+/// Decodes a null-terminated string, trying UTF-8, then CP949, then
+/// Windows-1252, and drops the color and formatting codes the game uses in
+/// map titles and chat
+pub fn decode(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0);
+    let cut = end.is_none();
+    decode_code_page(&bytes[..end.unwrap_or(bytes.len())], cut)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect()
+}
+
+/// Text that fills a fixed-size field, with no terminator, can be `cut` in
+/// the middle of its last character. That character is dropped instead of
+/// failing the whole string over to the next code page.
+fn decode_code_page(bytes: &[u8], cut: bool) -> Cow<'_, str> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => return Cow::Borrowed(text),
+        Err(e) if cut && e.error_len().is_none() => {
+            return String::from_utf8_lossy(&bytes[..e.valid_up_to()]);
+        }
+        Err(_) => {}
+    }
+
+    let cp949 = EUC_KR
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .or_else(|| {
+            let (&last, rest) = bytes.split_last()?;
+            // a lone lead byte
+            if !cut || last < 0x81 {
+                return None;
+            }
+            EUC_KR.decode_without_bom_handling_and_without_replacement(rest)
+        });
+    match cp949 {
+        Some(text) => Cow::Owned(text.into_owned()),
+        None => WINDOWS_1252.decode_without_bom_handling(bytes).0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8() {
+        assert_eq!(decode("안녕 gg\0junk".as_bytes()), "안녕 gg");
+        assert_eq!(decode("Über".as_bytes()), "Über");
+    }
+
+    #[test]
+    fn cp949() {
+        // "안녕"
+        assert_eq!(decode(&[0xbe, 0xc8, 0xb3, 0xe7, 0]), "안녕");
+    }
+
+    #[test]
+    fn windows_1252() {
+        assert_eq!(decode(b"caf\xe9!\0"), "café!");
+        assert_eq!(decode(b"caf\xe9\0"), "café");
+    }
+
+    #[test]
+    fn characters_cut_by_a_full_field_are_dropped() {
+        let utf8 = "안녕".as_bytes();
+        assert_eq!(decode(&utf8[..utf8.len() - 1]), "안");
+        assert_eq!(decode(&[0xbe, 0xc8, 0xb3]), "안");
+    }
+
+    #[test]
+    fn color_codes_are_dropped() {
+        assert_eq!(decode(b"\x03Fighting \x04Spirit\0"), "Fighting Spirit");
+    }
+}
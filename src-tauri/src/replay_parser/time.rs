@@ -0,0 +1,76 @@
+use crate::replay_parser::GameSpeed;
+
+/// Converts frame numbers into game time for the speed a replay was played at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameClock {
+    ms_per_frame: u32,
+}
+
+impl FrameClock {
+    pub fn new(speed: GameSpeed) -> Self {
+        Self {
+            ms_per_frame: speed.ms_per_frame(),
+        }
+    }
+
+    /// Milliseconds since the start of the game
+    pub fn ms(&self, frame: u32) -> u32 {
+        frame.saturating_mul(self.ms_per_frame)
+    }
+
+    /// "MM:SS" since the start of the game
+    pub fn display(&self, frame: u32) -> String {
+        let total_seconds = self.ms(frame) / 1000;
+        format!("{:02}:{:02}", total_seconds / 60, total_seconds % 60)
+    }
+}
+
+impl GameSpeed {
+    /// Length of one game frame. Unknown speeds are treated as Fastest, the
+    /// only speed allowed on ladder.
+    pub fn ms_per_frame(&self) -> u32 {
+        match self {
+            GameSpeed::Slowest => 167,
+            GameSpeed::Slower => 111,
+            GameSpeed::Slow => 83,
+            GameSpeed::Normal => 67,
+            GameSpeed::Fast => 56,
+            GameSpeed::Faster => 48,
+            GameSpeed::Fastest | GameSpeed::Unknown(_) => 42,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fastest_frames_are_42_ms() {
+        let clock = FrameClock::new(GameSpeed::Fastest);
+        assert_eq!(clock.ms(0), 0);
+        assert_eq!(clock.ms(24), 1008);
+        // 10 minutes of game time
+        assert_eq!(clock.ms(14_286), 600_012);
+        assert_eq!(clock.display(14_286), "10:00");
+    }
+
+    #[test]
+    fn slower_speeds_have_longer_frames() {
+        assert_eq!(FrameClock::new(GameSpeed::Normal).ms(1000), 67_000);
+        assert_eq!(FrameClock::new(GameSpeed::Slowest).display(360), "01:00");
+        assert_eq!(
+            FrameClock::new(GameSpeed::Unknown(9)),
+            FrameClock::new(GameSpeed::Fastest)
+        );
+    }
+
+    #[test]
+    fn display_pads_and_does_not_overflow() {
+        let clock = FrameClock::new(GameSpeed::Fastest);
+        assert_eq!(clock.display(23), "00:00");
+        assert_eq!(clock.display(1440), "01:00");
+        assert_eq!(clock.ms(u32::MAX), u32::MAX);
+        assert_eq!(clock.display(143_000), "100:06");
+    }
+}
@@ -0,0 +1,79 @@
+use crate::replay_parser::{
+    extended,
+    section::{self, CHUNK_SIZE},
+    ParseError, ParsedReplay,
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+/// Serializes `replay` as a modern ("seRS") replay. Sections are compressed
+/// again with zlib, so the output is equivalent to the input rather than
+/// byte-identical.
+///
+/// The output is always in the modern format, whatever the input was: there
+/// is no PKWARE compressor, so a legacy ("reRS") replay comes back as a
+/// modern one, which only StarCraft 1.18 and later can load.
+pub fn write_replay(replay: &ParsedReplay) -> Result<Vec<u8>, ParseError> {
+    let commands = replay.commands.decompress(&mut Vec::new())?;
+    Ok(write_replay_with_commands(replay, &commands))
+}
+
+/// Like `write_replay`, but writes `commands` as the decompressed commands
+/// section instead of the replay's own. Also always in the modern format.
+pub fn write_replay_with_commands(replay: &ParsedReplay, commands: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_section(&mut body, &replay.game_info.to_bytes(), true);
+    write_section(&mut body, &(commands.len() as u32).to_le_bytes(), true);
+    write_section(&mut body, commands, true);
+    write_section(
+        &mut body,
+        &(replay.map.raw.len() as u32).to_le_bytes(),
+        true,
+    );
+    write_section(&mut body, &replay.map.raw, true);
+
+    for section in &replay.extended.raw {
+        let size_known = extended::known_size(&section.tag).is_some();
+        let mut data = Vec::new();
+        write_section(&mut data, &section.data, size_known);
+        body.extend_from_slice(&section.tag);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&data);
+    }
+
+    let mut out = Vec::with_capacity(body.len() + 20);
+    write_section(&mut out, b"seRS", true);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+
+/// Writes a checksum, the chunk count and the chunks. Chunks that would not
+/// shrink are stored as-is, unless the reader couldn't tell them apart from
+/// compressed ones: it goes by their size when it knows the section's, and
+/// by the lack of a zlib header otherwise.
+fn write_section(out: &mut Vec<u8>, data: &[u8], size_known: bool) {
+    out.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+    out.extend_from_slice(&(data.len().div_ceil(CHUNK_SIZE) as u32).to_le_bytes());
+
+    for chunk in data.chunks(CHUNK_SIZE) {
+        let compressed = compress_zlib(chunk);
+        let store = size_known || !section::has_zlib_header(chunk);
+        let stored = if !store || compressed.len() < chunk.len() {
+            compressed.as_slice()
+        } else {
+            chunk
+        };
+        out.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        out.extend_from_slice(stored);
+    }
+}
+
+fn compress_zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use cwal_app_lib::replay_parser::{
+    anonymize, AnonymizeOptions, ParseOptions, ParsedReplay, ReplayFormat, ReplayParser,
+};
+
+const USER_IDS: [u32; 2] = [0x1234_5678, 0x0abc_def0];
+const GAME_ID: [u8; 16] = *b"game-0123456789!";
+
+/// Sbat format version 1 for a game between the two sample players
+fn shieldbattery() -> Vec<u8> {
+    let mut data = vec![1, 0];
+    data.extend(13_000u32.to_le_bytes());
+    let mut version = [0u8; 16];
+    version[..5].copy_from_slice(b"9.0.0");
+    data.extend(version);
+    data.extend([0u8; 4 + 12]);
+    data.extend(GAME_ID);
+    for i in 0..8 {
+        data.extend(USER_IDS.get(i).copied().unwrap_or(0).to_le_bytes());
+    }
+    data.extend(3u32.to_le_bytes());
+    data
+}
+
+fn parse(bytes: &[u8]) -> ParsedReplay {
+    ReplayParser::new(bytes)
+        .with_options(ParseOptions::strict())
+        .parse()
+        .unwrap()
+}
+
+/// Everything the replay holds, decompressed
+fn contents(replay: &ParsedReplay) -> Vec<u8> {
+    let mut data = replay.game_info.raw.clone();
+    replay.each_command(|_, _| {});
+    for frame in replay.frames() {
+        data.extend(frame.data());
+    }
+    data.extend(&replay.map.raw);
+    for section in &replay.extended.raw {
+        data.extend(&section.data);
+    }
+    data
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn no_names_or_ids_remain() {
+    let mut bytes = common::sample(ReplayFormat::Modern);
+    let sbat = shieldbattery();
+    common::extended_section(&mut bytes, b"Sbat", &sbat, &sbat);
+
+    let original = parse(&bytes);
+    let mut identifying: Vec<Vec<u8>> = ["Alice", "Bob", "Host", "Title", "hello"]
+        .iter()
+        .map(|s| s.as_bytes().to_vec())
+        .collect();
+    identifying.extend(USER_IDS.iter().map(|id| id.to_le_bytes().to_vec()));
+    identifying.push(GAME_ID.to_vec());
+    let before = contents(&original);
+    for needle in &identifying {
+        assert!(contains(&before, needle), "{needle:?} not in the sample");
+    }
+
+    let options = AnonymizeOptions {
+        player_name: "P{n}".to_string(),
+        host_name: "H".to_string(),
+        title: "G".to_string(),
+        chat_message: "-".to_string(),
+    };
+    let written = anonymize(original, &options).unwrap();
+    let copy = parse(&written);
+    let after = contents(&copy);
+    for needle in &identifying {
+        assert!(!contains(&after, needle), "{needle:?} left in the copy");
+        assert!(!contains(&written, needle), "{needle:?} left in the file");
+    }
+
+    assert_eq!(copy.game_info.title, "G");
+    assert_eq!(copy.game_info.host_name, "H");
+    let names: Vec<&str> = copy.players().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["P1", "P2"]);
+    assert_eq!(copy.chat_messages()[0].message, "-");
+    let sbat = copy.extended.shieldbattery.unwrap();
+    assert_eq!(sbat.user_ids, [0; 8]);
+    assert_eq!(sbat.shieldbattery_version, "9.0.0");
+    assert_eq!(sbat.game_logic_version, Some(3));
+}
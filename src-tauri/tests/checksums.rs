@@ -0,0 +1,158 @@
+mod common;
+
+use cwal_app_lib::replay_parser::{
+    section::{Section, SectionReader},
+    ParseError, ParseOptions, ReplayFormat, ReplayParser,
+};
+
+fn verify(name: &str, bytes: &[u8]) {
+    let replay = ReplayParser::new(bytes)
+        .with_options(ParseOptions::strict())
+        .parse()
+        .unwrap_or_else(|e| panic!("{name}: {e}"));
+    replay
+        .commands
+        .check(&mut Vec::new())
+        .unwrap_or_else(|e| panic!("{name}: {e}"));
+}
+
+/// A modern sample with the checksum of the commands section, or of the map
+/// section, replaced
+fn damaged_sample(section: Section) -> Vec<u8> {
+    let info = common::game_info(&[(0, 2, 2, 1, "Alice"), (1, 2, 0, 2, "Bob")]);
+    let commands = common::frames(&[(10, vec![0, 0x57, 1])]);
+    let map = common::chk();
+    let mut bytes = common::replay(ReplayFormat::Modern, &info, &commands, &map);
+
+    let mut offset = 20;
+    let mut skip = |data: &[u8]| {
+        let mut section = Vec::new();
+        common::section(&mut section, ReplayFormat::Modern, data);
+        offset += section.len();
+    };
+    skip(&info);
+    skip(&(commands.len() as u32).to_le_bytes());
+    if section == Section::Map {
+        skip(&commands);
+        skip(&(map.len() as u32).to_le_bytes());
+    }
+    bytes[offset] ^= 0xff;
+    bytes
+}
+
+#[test]
+fn checksums_match_on_samples() {
+    verify("modern", &common::sample(ReplayFormat::Modern));
+    verify("legacy", &common::sample(ReplayFormat::Legacy));
+}
+
+#[test]
+fn checksums_are_standard_crc32() {
+    // The published check value of CRC-32/ISO-HDLC, which zlib uses
+    let mut section = Vec::new();
+    section.extend(0xcbf4_3926u32.to_le_bytes());
+    section.extend(1u32.to_le_bytes());
+    section.extend(9u32.to_le_bytes());
+    section.extend(b"123456789");
+
+    let reader = SectionReader {
+        format: ReplayFormat::Modern,
+        options: ParseOptions::strict(),
+    };
+    let (_, data) = reader
+        .read(&section, 9, Section::GameInfo, &mut Vec::new())
+        .unwrap();
+    assert_eq!(data, b"123456789");
+}
+
+#[test]
+fn mismatches_are_errors_in_strict_mode() {
+    for section in [Section::Commands, Section::Map] {
+        let error = ReplayParser::new(&damaged_sample(section))
+            .with_options(ParseOptions::strict())
+            .parse()
+            .unwrap_err();
+        assert!(
+            matches!(error, ParseError::ChecksumMismatch { section: s, .. } if s == section),
+            "{error:?}"
+        );
+    }
+}
+
+#[test]
+fn mismatches_are_warnings_in_lenient_mode() {
+    let replay = ReplayParser::new(&damaged_sample(Section::Map))
+        .with_options(ParseOptions::lenient())
+        .parse()
+        .unwrap();
+    let warnings: Vec<_> = replay.warnings().collect();
+    assert!(matches!(
+        warnings[..],
+        [ParseError::ChecksumMismatch {
+            section: Section::Map,
+            ..
+        }]
+    ));
+    assert_eq!(replay.map.scenario_name, "Name");
+
+    let replay = ReplayParser::new(&damaged_sample(Section::Commands))
+        .with_options(ParseOptions::lenient())
+        .parse()
+        .unwrap();
+    assert_eq!(replay.map.scenario_name, "Name");
+    // Only found once the commands are read
+    assert_eq!(replay.warnings().count(), 0);
+    let mut commands = 0;
+    replay.each_command(|_, _| commands += 1);
+    assert_eq!(commands, 1);
+    let warnings: Vec<_> = replay.warnings().collect();
+    assert!(matches!(
+        warnings[..],
+        [ParseError::ChecksumMismatch {
+            section: Section::Commands,
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn undecompressable_chunks_are_warnings_in_lenient_mode() {
+    let info = common::game_info(&[(0, 2, 2, 1, "Alice"), (1, 2, 0, 2, "Bob")]);
+    // Compressible, so the chunk is stored compressed
+    let commands = common::frames(&vec![(10, vec![0, 0x05]); 40]);
+    let mut bytes = common::replay(ReplayFormat::Modern, &info, &commands, &common::chk());
+
+    let mut prefix = Vec::new();
+    common::section(&mut prefix, ReplayFormat::Modern, &info);
+    let size = (commands.len() as u32).to_le_bytes();
+    common::section(&mut prefix, ReplayFormat::Modern, &size);
+    // past the checksum, chunk count, chunk size and zlib header
+    let chunk = 20 + prefix.len() + 14;
+    bytes[chunk..chunk + 4].fill(0xff);
+
+    let replay = ReplayParser::new(&bytes)
+        .with_options(ParseOptions::lenient())
+        .parse()
+        .unwrap();
+    let mut commands = 0;
+    replay.each_command(|_, _| commands += 1);
+    assert_eq!(commands, 0);
+    let warnings: Vec<_> = replay.warnings().collect();
+    assert!(
+        matches!(warnings[..], [ParseError::InvalidData { .. }]),
+        "{warnings:?}"
+    );
+}
+
+/// Real replays of every kind: legacy, modern, and modern with extended
+/// sections
+#[test]
+fn checksums_match_on_fixtures() {
+    let fixtures = common::fixtures();
+    if fixtures.is_empty() {
+        eprintln!("no replays in tests/fixtures");
+    }
+    for (name, bytes) in fixtures {
+        verify(&name, &bytes);
+    }
+}
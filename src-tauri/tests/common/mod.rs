@@ -0,0 +1,234 @@
+//! Builds small replays for the integration tests. Real replays to test
+//! against go in `tests/fixtures`.
+
+#![allow(dead_code)]
+
+use cwal_app_lib::replay_parser::ReplayFormat;
+use flate2::{write::ZlibEncoder, Compression};
+use std::{fs, io::Write, path::PathBuf};
+
+/// Replays in `tests/fixtures`, with their file names
+pub fn fixtures() -> Vec<(String, Vec<u8>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    paths.retain(|p| {
+        p.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("rep"))
+    });
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|p| {
+            let name = p.file_name().unwrap().to_string_lossy().to_string();
+            (name, fs::read(&p).unwrap())
+        })
+        .collect()
+}
+
+/// A 1v1 with a few commands and chat, in either format
+pub fn sample(format: ReplayFormat) -> Vec<u8> {
+    let info = game_info(&[(0, 2, 2, 1, "Alice"), (1, 2, 0, 2, "Bob")]);
+    let mut chat = vec![0u8, 0x5c, 1];
+    let mut message = [0u8; 80];
+    message[..5].copy_from_slice(b"hello");
+    chat.extend(message);
+    let commands = frames(&[
+        // select one unit, then train an SCV
+        (10, vec![0, 0x09, 1, 5, 0, 0, 0x1f, 0x07, 0]),
+        (
+            24,
+            vec![0, 0x0c, 6, 10, 0, 20, 0, 0x9a, 0, 1, 0x63, 1, 7, 0, 0, 0],
+        ),
+        (30, chat),
+        (40, vec![1, 0x57, 1]),
+    ]);
+    replay(format, &info, &commands, &chk())
+}
+
+/// Game info for a melee game on Fastest, `players` being (id, type,
+/// race, team, name)
+pub fn game_info(players: &[(u8, u8, u8, u8, &str)]) -> Vec<u8> {
+    let mut h = vec![0u8; 0x279];
+    h[0] = 1;
+    h[1..5].copy_from_slice(&30000u32.to_le_bytes());
+    h[8..12].copy_from_slice(&1_700_000_000u32.to_le_bytes());
+    h[0x18..0x18 + 5].copy_from_slice(b"Title");
+    h[0x34..0x36].copy_from_slice(&128u16.to_le_bytes());
+    h[0x36..0x38].copy_from_slice(&96u16.to_le_bytes());
+    h[0x39] = 2;
+    h[0x3a] = 6;
+    h[0x3c..0x3e].copy_from_slice(&0x02u16.to_le_bytes());
+    h[0x48..0x48 + 4].copy_from_slice(b"Host");
+    h[0x61..0x61 + 7].copy_from_slice(b"Polypoi");
+    for i in 0..12 {
+        let o = 0xa1 + i * 36;
+        h[o..o + 2].copy_from_slice(&(i as u16).to_le_bytes());
+        h[o + 4] = i as u8;
+        if let Some(&(id, player_type, race, team, name)) = players.get(i) {
+            h[o + 4] = id;
+            h[o + 8] = player_type;
+            h[o + 9] = race;
+            h[o + 10] = team;
+            h[o + 11..o + 11 + name.len()].copy_from_slice(name.as_bytes());
+        }
+    }
+    for i in 0..8 {
+        let o = 0x251 + i * 4;
+        h[o..o + 4].copy_from_slice(&(i as u32).to_le_bytes());
+    }
+    h
+}
+
+/// The commands section: each frame number with its block of commands
+pub fn frames(frames: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (frame, block) in frames {
+        out.extend(frame.to_le_bytes());
+        out.push(block.len() as u8);
+        out.extend(block);
+    }
+    out
+}
+
+/// A 128x96 map with one unit, strings and scenario properties
+pub fn chk() -> Vec<u8> {
+    let mut chk = Vec::new();
+    let mut section = |name: &[u8], body: Vec<u8>| {
+        chk.extend(name);
+        chk.extend((body.len() as u32).to_le_bytes());
+        chk.extend(body);
+    };
+    section(b"VER ", 205u16.to_le_bytes().to_vec());
+    section(
+        b"DIM ",
+        [128u16.to_le_bytes(), 96u16.to_le_bytes()].concat(),
+    );
+    section(b"ERA ", 4u16.to_le_bytes().to_vec());
+    let mut unit = vec![0u8; 36];
+    unit[4..6].copy_from_slice(&100u16.to_le_bytes());
+    unit[6..8].copy_from_slice(&200u16.to_le_bytes());
+    unit[8..10].copy_from_slice(&214u16.to_le_bytes());
+    unit[16] = 1;
+    section(b"UNIT", unit);
+    let mut strings = Vec::new();
+    strings.extend(2u16.to_le_bytes());
+    strings.extend(6u16.to_le_bytes());
+    strings.extend(12u16.to_le_bytes());
+    strings.extend(b"Name\0\0Desc\0");
+    section(b"STR ", strings);
+    section(b"SPRP", [1u16.to_le_bytes(), 2u16.to_le_bytes()].concat());
+    chk
+}
+
+/// A whole replay. Legacy replays have no extended sections.
+pub fn replay(format: ReplayFormat, info: &[u8], commands: &[u8], map: &[u8]) -> Vec<u8> {
+    // The replay id is too small to compress, so it is always stored as-is
+    let mut out = Vec::new();
+    let id: &[u8; 4] = match format {
+        ReplayFormat::Modern => b"seRS",
+        ReplayFormat::Legacy => b"reRS",
+    };
+    out.extend(crc32fast::hash(id).to_le_bytes());
+    out.extend(1u32.to_le_bytes());
+    out.extend(4u32.to_le_bytes());
+    out.extend(id);
+    if format == ReplayFormat::Modern {
+        out.extend(0u32.to_le_bytes());
+    }
+    section(&mut out, format, info);
+    section(&mut out, format, &(commands.len() as u32).to_le_bytes());
+    section(&mut out, format, commands);
+    section(&mut out, format, &(map.len() as u32).to_le_bytes());
+    section(&mut out, format, map);
+    out
+}
+
+/// Appends a section: CRC32, chunk count, then each compressed chunk
+/// prefixed by its length
+pub fn section(out: &mut Vec<u8>, format: ReplayFormat, data: &[u8]) {
+    out.extend(crc32fast::hash(data).to_le_bytes());
+    let chunks: Vec<&[u8]> = data.chunks(8192).collect();
+    out.extend((chunks.len() as u32).to_le_bytes());
+    for chunk in chunks {
+        let stored = match format {
+            ReplayFormat::Modern => {
+                let compressed = zlib(chunk);
+                if compressed.len() < chunk.len() {
+                    compressed
+                } else {
+                    chunk.to_vec()
+                }
+            }
+            // never the size of the chunk, which would mean stored as-is
+            ReplayFormat::Legacy => implode(chunk),
+        };
+        out.extend((stored.len() as u32).to_le_bytes());
+        out.extend(stored);
+    }
+}
+
+/// Appends an extended section with `tag` whose single chunk is `chunk`
+/// as stored
+pub fn extended_section(replay: &mut Vec<u8>, tag: &[u8; 4], data: &[u8], chunk: &[u8]) {
+    let mut section = Vec::new();
+    section.extend(crc32fast::hash(data).to_le_bytes());
+    section.extend(1u32.to_le_bytes());
+    section.extend((chunk.len() as u32).to_le_bytes());
+    section.extend(chunk);
+    replay.extend(tag);
+    replay.extend((section.len() as u32).to_le_bytes());
+    replay.extend(section);
+}
+
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// PKWARE DCL implode using only uncoded literals, the simplest stream
+/// `explode` accepts
+pub fn implode(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.write(0, 8); // uncoded literals
+    bits.write(6, 8); // 4 KiB dictionary
+    for &byte in data {
+        bits.write(0, 1);
+        bits.write(byte as u32, 8);
+    }
+    // End of stream: a length of 519, which is length symbol 15 (code
+    // 1111111, stored inverted) plus 8 extra bits of 255
+    bits.write(1, 1);
+    bits.write(0, 7);
+    bits.write(0xff, 8);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buf: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Least significant bit first
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buf |= value << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buf as u8);
+        }
+        self.out
+    }
+}
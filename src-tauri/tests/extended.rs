@@ -0,0 +1,55 @@
+mod common;
+
+use cwal_app_lib::replay_parser::{ParseError, ParseOptions, ReplayFormat, ReplayParser};
+
+/// Sbat format version 1, which starts with no zlib header
+fn shieldbattery() -> Vec<u8> {
+    let mut data = vec![1, 0];
+    data.extend(13_000u32.to_le_bytes());
+    let mut version = [0u8; 16];
+    version[..5].copy_from_slice(b"9.0.0");
+    data.extend(version);
+    data.extend([0u8; 4 + 12 + 16 + 32]);
+    data.extend(3u32.to_le_bytes());
+    data
+}
+
+#[test]
+fn stored_sbat_chunk_is_read_in_strict_mode() {
+    let data = shieldbattery();
+    let mut bytes = common::sample(ReplayFormat::Modern);
+    common::extended_section(&mut bytes, b"Sbat", &data, &data);
+
+    let replay = ReplayParser::new(&bytes)
+        .with_options(ParseOptions::strict())
+        .parse()
+        .unwrap();
+    let sbat = replay.extended.shieldbattery.unwrap();
+    assert_eq!(sbat.shieldbattery_version, "9.0.0");
+    assert_eq!(sbat.game_logic_version, Some(3));
+    assert_eq!(replay.extended.raw[0].data, data);
+}
+
+#[test]
+fn damaged_extended_section_is_never_kept_as_data() {
+    let data = shieldbattery();
+    // a zlib header followed by garbage
+    let mut chunk = vec![0x78, 0x9c];
+    chunk.extend([0xff; 16]);
+    let mut bytes = common::sample(ReplayFormat::Modern);
+    common::extended_section(&mut bytes, b"Sbat", &data, &chunk);
+
+    let error = ReplayParser::new(&bytes)
+        .with_options(ParseOptions::strict())
+        .parse()
+        .unwrap_err();
+    assert!(matches!(error, ParseError::InvalidData { .. }), "{error:?}");
+
+    let replay = ReplayParser::new(&bytes)
+        .with_options(ParseOptions::lenient())
+        .parse()
+        .unwrap();
+    assert!(replay.extended.raw.is_empty());
+    assert!(replay.extended.shieldbattery.is_none());
+    assert_eq!(replay.warnings().count(), 1);
+}
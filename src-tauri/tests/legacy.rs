@@ -0,0 +1,52 @@
+mod common;
+
+use cwal_app_lib::replay_parser::{Command, ParseOptions, ReplayFormat, ReplayParser};
+
+#[test]
+fn legacy_replay_decodes_end_to_end() {
+    let modern = common::sample(ReplayFormat::Modern);
+    let legacy = common::sample(ReplayFormat::Legacy);
+    assert_eq!(&legacy[12..16], b"reRS");
+
+    let parse = |bytes: &[u8]| {
+        ReplayParser::new(bytes)
+            .with_options(ParseOptions {
+                strict: true,
+                ..ParseOptions::default()
+            })
+            .parse()
+            .unwrap()
+    };
+    let modern = parse(&modern);
+    let replay = parse(&legacy);
+
+    assert_eq!(replay.game_info.raw, modern.game_info.raw);
+    assert_eq!(replay.game_info.map_name, "Polypoi");
+    let names: Vec<_> = replay.players().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["Alice", "Bob"]);
+
+    let mut commands = Vec::new();
+    for frame in replay.frames() {
+        for command in frame.commands() {
+            let leave = matches!(command.command, Command::LeaveGame { reason: 1 });
+            commands.push((frame.frame_number, command.player_id, leave));
+        }
+    }
+    assert_eq!(commands.len(), 6);
+    assert_eq!(commands[5], (40, 1, true));
+
+    assert_eq!(replay.chat_messages()[0].message, "hello");
+    assert_eq!((replay.map.width, replay.map.height), (128, 96));
+}
+
+#[test]
+fn damaged_legacy_chunk_is_an_error_in_strict_mode() {
+    let mut legacy = common::sample(ReplayFormat::Legacy);
+    // the dictionary size byte of the game info's only chunk
+    let game_info_chunk = 16 + 8 + 4;
+    legacy[game_info_chunk + 1] = 9;
+    let result = ReplayParser::new(&legacy)
+        .with_options(ParseOptions::strict())
+        .parse();
+    assert!(result.is_err());
+}
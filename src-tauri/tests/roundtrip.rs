@@ -0,0 +1,85 @@
+mod common;
+
+use cwal_app_lib::replay_parser::{
+    write_replay, ParseOptions, ParsedReplay, ReplayFormat, ReplayParser,
+};
+
+fn parse(name: &str, bytes: &[u8], options: ParseOptions) -> ParsedReplay {
+    ReplayParser::new(bytes)
+        .with_options(options)
+        .parse()
+        .unwrap_or_else(|e| panic!("{name}: {e}"))
+}
+
+fn frames(replay: &ParsedReplay) -> Vec<(u32, Vec<u8>)> {
+    replay
+        .frames()
+        .map(|frame| (frame.frame_number, frame.data().to_vec()))
+        .collect()
+}
+
+/// Parses `bytes`, writes the replay out again and checks that the output
+/// parses with checksums verified into the same replay
+fn round_trip(name: &str, bytes: &[u8]) {
+    let original = parse(name, bytes, ParseOptions::lenient());
+    // Damage to the commands is only found by reading them
+    original.each_command(|_, _| {});
+    let warnings: Vec<_> = original.warnings().collect();
+    assert!(warnings.is_empty(), "{name}: {warnings:?}");
+
+    let written = write_replay(&original).unwrap_or_else(|e| panic!("{name}: {e}"));
+    let copy = parse(name, &written, ParseOptions::strict());
+
+    assert_eq!(copy.game_info.raw, original.game_info.raw, "{name}");
+    assert_eq!(frames(&copy), frames(&original), "{name}");
+    assert_eq!(copy.map.raw, original.map.raw, "{name}");
+    let extended = |replay: &ParsedReplay| {
+        replay
+            .extended
+            .raw
+            .iter()
+            .map(|s| (s.tag, s.data.clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(extended(&copy), extended(&original), "{name}");
+    let warnings: Vec<_> = copy.warnings().collect();
+    assert!(warnings.is_empty(), "{name}: {warnings:?}");
+}
+
+#[test]
+fn modern_replay_round_trips() {
+    let mut bytes = common::sample(ReplayFormat::Modern);
+    let limits: Vec<u8> = (1..=7u32).flat_map(|n| (n * 1000).to_le_bytes()).collect();
+    common::extended_section(&mut bytes, b"LMTS", &limits, &common::zlib(&limits));
+    // unknown tags with data that compresses and data that does not
+    let text = b"abcabcabcabcabcabcabcabcabcabcabcabc".to_vec();
+    common::extended_section(&mut bytes, b"Xtra", &text, &common::zlib(&text));
+    let noise: Vec<u8> = (0..64u32).map(|n| (n * 37 % 251) as u8 | 1).collect();
+    common::extended_section(&mut bytes, b"Xraw", &noise, &noise);
+
+    round_trip("modern", &bytes);
+}
+
+#[test]
+fn legacy_replay_round_trips() {
+    round_trip("legacy", &common::sample(ReplayFormat::Legacy));
+}
+
+#[test]
+fn legacy_replays_are_written_in_the_modern_format() {
+    let bytes = common::sample(ReplayFormat::Legacy);
+    let written = write_replay(&parse("legacy", &bytes, ParseOptions::strict())).unwrap();
+    assert_eq!(&bytes[12..16], b"reRS");
+    assert_eq!(&written[12..16], b"seRS");
+}
+
+#[test]
+fn fixtures_round_trip() {
+    let fixtures = common::fixtures();
+    if fixtures.is_empty() {
+        eprintln!("no replays in tests/fixtures");
+    }
+    for (name, bytes) in fixtures {
+        round_trip(&name, &bytes);
+    }
+}